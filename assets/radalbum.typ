@@ -0,0 +1,105 @@
+// Template of the Typst album written by radalbum.
+// Compile the album with `typst compile Album.typ`.
+// Copy this file and pass it with --typst-template to change the look of the album. The album
+// calls every function below, so a copy must keep their names and parameters.
+
+// Colors of the album, matching Album.css
+#let heading-color = rgb("#361E20")
+#let text-color = rgb("#171D1A")
+#let box-color = rgb("#F5F0E9")
+
+// Space between two photos of a row
+#let gap = 4mm
+
+// Maximum height of a photo
+#let max-photo-height = 17cm
+
+#let album(title: "", body) = {
+  set document(title: title)
+  set page(paper: "a4", margin: 15mm, numbering: "1")
+  set text(font: ("DejaVu Sans", "Arial"), size: 10pt, fill: text-color)
+  align(center, text(size: 28pt, weight: "bold", fill: heading-color, title))
+  body
+}
+
+// A photo, as wide as possible without getting higher than the maximum photo height
+#let photo-image(photo) = layout(size => {
+  let width = calc.min(size.width, max-photo-height * photo.width / photo.height)
+  align(center, image(photo.path, width: width))
+})
+
+// Photos next to each other in one row
+#let photo-row(..photos) = grid(
+  columns: (1fr,) * photos.pos().len(),
+  column-gutter: gap,
+  ..photos.pos().map(photo-image),
+)
+
+// Rows of photos with the title and the caption below them, which are never separated
+#let photo-block(rows, title: "", caption: "") = block(breakable: false, width: 100%, {
+  for row in rows {
+    photo-row(..row)
+    v(gap, weak: true)
+  }
+  if title != "" {
+    text(size: 14pt, weight: "bold", title)
+    parbreak()
+  }
+  if caption != "" {
+    block(fill: box-color, inset: 3mm, width: 100%, caption)
+  }
+  v(gap)
+})
+
+#let single-photo(photo, title: "", caption: "") = photo-block(
+  ((photo,),), title: title, caption: caption,
+)
+
+#let two-photos(photo1, photo2, title: "", caption: "") = photo-block(
+  ((photo1, photo2),), title: title, caption: caption,
+)
+
+#let three-photos(photo1, photo2, photo3, title: "", caption: "") = photo-block(
+  ((photo1, photo2, photo3),), title: title, caption: caption,
+)
+
+#let four-photos(photo1, photo2, photo3, photo4, title: "", caption: "") = photo-block(
+  ((photo1, photo2), (photo3, photo4)), title: title, caption: caption,
+)
+
+// A day of travel, which starts on a new page
+#let day-header(
+  title: "",
+  from: none,
+  to: none,
+  distance: none,
+  ascent: none,
+  descent: none,
+  moving-time: none,
+  avg-speed: none,
+  photo: none,
+) = {
+  pagebreak(weak: true)
+  block(breakable: false, width: 100%, {
+    text(size: 22pt, weight: "bold", fill: heading-color, title)
+    let route = (from, to).filter(place => place != none)
+    let stats = (
+      if distance != none { "⇄ " + distance + " km" },
+      if ascent != none { "↗ " + ascent + " m" },
+      if descent != none { "↘ " + descent + " m" },
+      if moving-time != none { "⏲ " + moving-time },
+      if avg-speed != none { "⌀ " + avg-speed + " km/h" },
+    ).filter(stat => stat != none)
+    if route.len() > 0 or stats.len() > 0 {
+      block(fill: box-color, inset: 3mm, width: 100%, {
+        if route.len() > 0 { [↪ #route.join(" → ")] }
+        if route.len() > 0 and stats.len() > 0 { linebreak() }
+        stats.join("   ")
+      })
+    }
+    if photo != none {
+      photo-image(photo)
+    }
+    v(gap)
+  })
+}
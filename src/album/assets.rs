@@ -0,0 +1,174 @@
+use crate::album::theme::Theme;
+use std::borrow::Cow;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// The default assets, compiled into the program, by their file names.
+const DEFAULT_ASSETS: [(&str, &[u8]); 16] = [
+    ("Album.css", include_bytes!("../../assets/Album.css")),
+    (
+        "theme-classic.css",
+        include_bytes!("../../assets/theme-classic.css"),
+    ),
+    (
+        "theme-minimal.css",
+        include_bytes!("../../assets/theme-minimal.css"),
+    ),
+    (
+        "theme-dark.css",
+        include_bytes!("../../assets/theme-dark.css"),
+    ),
+    (
+        "theme-print.css",
+        include_bytes!("../../assets/theme-print.css"),
+    ),
+    ("radalbum.cls", include_bytes!("../../assets/radalbum.cls")),
+    ("radalbum.typ", include_bytes!("../../assets/radalbum.typ")),
+    (
+        "templates/caption.md",
+        include_bytes!("../../assets/templates/caption.md"),
+    ),
+    (
+        "templates/single-photo.md",
+        include_bytes!("../../assets/templates/single-photo.md"),
+    ),
+    (
+        "templates/two-photos.md",
+        include_bytes!("../../assets/templates/two-photos.md"),
+    ),
+    (
+        "templates/three-photos.md",
+        include_bytes!("../../assets/templates/three-photos.md"),
+    ),
+    (
+        "templates/four-photos.md",
+        include_bytes!("../../assets/templates/four-photos.md"),
+    ),
+    (
+        "templates/day-header.md",
+        include_bytes!("../../assets/templates/day-header.md"),
+    ),
+    (
+        "templates/album.md",
+        include_bytes!("../../assets/templates/album.md"),
+    ),
+    (
+        "fonts/DejaVuSans.ttf",
+        include_bytes!("../../assets/fonts/DejaVuSans.ttf"),
+    ),
+    (
+        "fonts/DejaVuSans-Bold.ttf",
+        include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf"),
+    ),
+];
+
+/// The font of texts that have no font set explicitly, DejaVu Sans.
+pub const DEFAULT_FONT: &str = "fonts/DejaVuSans.ttf";
+
+/// The bold font of texts that have no bold font set explicitly.
+pub const DEFAULT_BOLD_FONT: &str = "fonts/DejaVuSans-Bold.ttf";
+
+/// A file that is used to build the album, e.g. a stylesheet or a template.
+#[derive(Clone)]
+pub struct AssetFile {
+    /// File name the asset is written with into the output directory
+    pub name: String,
+    pub content: Cow<'static, [u8]>,
+}
+
+impl AssetFile {
+    /// Load the file at the given path.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let name = path.file_name().ok_or(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Not a file: {}", path.display()),
+        ))?;
+        let content = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Could not read {}: {}", path.display(), e),
+            )
+        })?;
+        Ok(Self {
+            name: name.to_string_lossy().into_owned(),
+            content: Cow::Owned(content),
+        })
+    }
+}
+
+/// The assets the album is built with, and the theme of its stylesheet.
+/// All default assets are compiled into the program. Each of them can be replaced by a file with
+/// the same name inside the override directory, while all other assets keep their default.
+pub struct Assets {
+    /// Directory with the assets that replace the default assets
+    dir: Option<PathBuf>,
+    theme: Theme,
+}
+
+impl Assets {
+    /// The assets with the given override directory, or with the override directory of the user
+    /// if none is given.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir: dir.or_else(Self::user_dir),
+            theme: Theme::default(),
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// The override directory of the user, `radalbum/assets` inside the configuration directory:
+    /// `$XDG_CONFIG_HOME/radalbum/assets` or `~/.config/radalbum/assets`, and
+    /// `%APPDATA%\radalbum\assets` on Windows.
+    pub fn user_dir() -> Option<PathBuf> {
+        let config = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config.join("radalbum").join("assets"))
+    }
+
+    /// The override directory, if it exists.
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref().filter(|dir| dir.is_dir())
+    }
+
+    /// The font file at the given path, or the bundled regular or bold default font.
+    /// All fonts of the album, e.g. of the PDF album and of watermarks, are looked up here.
+    pub fn font(&self, path: Option<&Path>, bold: bool) -> io::Result<AssetFile> {
+        match path {
+            Some(path) => AssetFile::load(path),
+            None if bold => self.get(DEFAULT_BOLD_FONT),
+            None => self.get(DEFAULT_FONT),
+        }
+    }
+
+    /// The asset with the given file name, from the override directory if it contains it.
+    pub fn get(&self, name: &str) -> io::Result<AssetFile> {
+        if let Some(path) = self.dir().map(|dir| dir.join(name)) {
+            if path.is_file() {
+                return AssetFile::load(&path);
+            }
+        }
+        DEFAULT_ASSETS
+            .iter()
+            .find(|(default, _)| *default == name)
+            .map(|(_, content)| AssetFile {
+                name: name.to_string(),
+                content: Cow::Borrowed(*content),
+            })
+            .ok_or(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Unknown asset: {}", name),
+            ))
+    }
+}
@@ -0,0 +1,36 @@
+use crate::album::theme::Theme;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The settings of the album that are read from the album config file (TOML).
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlbumConfig {
+    /// Title of the album, printed at its beginning
+    pub title: Option<String>,
+    pub theme: Theme,
+}
+
+impl AlbumConfig {
+    /// Load the album config at the given path. Paths inside the config are relative to the
+    /// directory of the config.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Could not read {}: {}", path.display(), e),
+            )
+        })?;
+        let mut config = toml::from_str::<Self>(&content).map_err(|e| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid album config {}: {}", path.display(), e),
+            )
+        })?;
+        config.theme.base = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        Ok(config)
+    }
+}
@@ -0,0 +1,390 @@
+pub(crate) mod zip;
+
+use crate::album::epub::zip::ZipWriter;
+use crate::album::pdf::{PrintBlock, PrintLayout};
+use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::website::{escape, slug_with};
+use crate::album::Album;
+use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::SystemTime;
+use std::{fs, io};
+
+/// Language of the album text.
+const LANGUAGE: &str = "en";
+
+/// Stylesheet of all pages of the EPUB album.
+const STYLESHEET: &str = r#"body { margin: 0 0.5em; font-family: sans-serif; }
+h1, h2 { color: #361E20; }
+h1 { text-align: center; }
+.details { margin: 0.2em 0; }
+.row { display: flex; gap: 0.5em; margin: 1em 0 0.5em; }
+.row img { flex: 1; min-width: 0; width: 100%; height: auto; }
+.row-1 img { max-height: 95vh; object-fit: contain; }
+.title { font-weight: bold; margin: 0.3em 0; }
+.caption { background: #F5F0E9; color: #171D1A; padding: 0.5em; }
+.caption > * { margin: 0.3em 0; }
+.cover { text-align: center; }
+.cover img { max-width: 100%; max-height: 95vh; }
+"#;
+
+/// One chapter of the EPUB album, which is a single XHTML page.
+struct Chapter {
+    title: String,
+    body: String,
+}
+
+/// A complete XHTML page with the given title and body.
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{0}" lang="{0}">
+<head>
+<meta charset="UTF-8"/>
+<title>{1}</title>
+<link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{2}</body>
+</html>
+"#,
+        LANGUAGE,
+        escape(title),
+        body
+    )
+}
+
+/// Format the given time as UTC timestamp, as required for the modification date of the EPUB.
+fn format_utc(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, rest) = ((seconds / 86400) as i64, seconds % 86400);
+    // Convert the days since 1970-01-01 into a civil date
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest / 60 % 60,
+        rest % 60
+    )
+}
+
+/// Collects the images of the EPUB album, each under a unique name inside the archive.
+/// The names are slugs of the file names, so that they can be used in links without encoding.
+#[derive(Default)]
+struct Images<'a> {
+    /// Name inside the archive of every image, by its path
+    names: BTreeMap<&'a Path, String>,
+}
+
+impl<'a> Images<'a> {
+    /// The path of the given photo relative to the pages.
+    fn add(&mut self, photo: &'a Photo) -> io::Result<String> {
+        let path = photo.get_path();
+        if let Some(name) = self.names.get(path) {
+            return Ok(format!("images/{}", name));
+        }
+        let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Invalid Path in image detected!",
+            ));
+        };
+        let mut stem = slug_with(stem, '_');
+        if stem.is_empty() {
+            stem.push_str("photo");
+        }
+        let extension = slug_with(extension, '_').to_lowercase();
+        // Photos of different folders may have the same name
+        let mut name = format!("{}.{}", stem, extension);
+        let mut counter = 1;
+        while self.names.values().any(|existing| *existing == name) {
+            name = format!("{}_{}.{}", stem, counter, extension);
+            counter += 1;
+        }
+        self.names.insert(path, name.clone());
+        Ok(format!("images/{}", name))
+    }
+
+    fn media_type(name: &str) -> &'static str {
+        if name.to_lowercase().ends_with(".png") {
+            "image/png"
+        } else {
+            "image/jpeg"
+        }
+    }
+}
+
+/// Write the given blocks of a photo container as XHTML.
+fn write_blocks<'a>(
+    layout: &PrintLayout<'a>,
+    images: &mut Images<'a>,
+    out: &mut String,
+) -> io::Result<()> {
+    // Writing into a string cannot fail
+    for block in &layout.blocks {
+        match block {
+            PrintBlock::Heading(text) => {
+                let _ = writeln!(out, "<h2>{}</h2>", escape(text));
+            },
+            PrintBlock::Details(text) => {
+                let _ = writeln!(out, "<p class=\"details\">{}</p>", escape(text));
+            },
+            PrintBlock::Row(photos) => {
+                let _ = writeln!(out, "<div class=\"row row-{}\">", photos.len());
+                for &photo in photos {
+                    let _ = writeln!(
+                        out,
+                        "<img src=\"{}\" alt=\"{}\"/>",
+                        escape(&images.add(photo)?),
+                        escape(&photo.get_html_escaped_title())
+                    );
+                }
+                let _ = writeln!(out, "</div>");
+            },
+            PrintBlock::Title(text) => {
+                let _ = writeln!(out, "<p class=\"title\">{}</p>", escape(text));
+            },
+            PrintBlock::Caption { photo, .. } => {
+                let _ = writeln!(
+                    out,
+                    "<div class=\"caption\">\n{}</div>",
+                    photo.get_rendered_caption()
+                );
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Write the given containers as EPUB 3 album to the given path.
+/// Every day starts a new chapter. The album title is used as title of the book, and the first
+/// photo of the album as its cover. The file is only rewritten if its content changed.
+pub fn write_epub(
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    path: &Path,
+) -> io::Result<()> {
+    let layouts = containers
+        .iter()
+        .map(|container| container.print_layout())
+        .collect::<Vec<_>>();
+    let mut images = Images::default();
+    let mut chapters: Vec<Chapter> = vec![];
+    for layout in &layouts {
+        if layout.chapter || chapters.is_empty() {
+            let heading = layout
+                .blocks
+                .iter()
+                .find_map(|block| match block {
+                    PrintBlock::Heading(heading) if layout.chapter => Some(heading.clone()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| title.to_string());
+            chapters.push(Chapter {
+                title: heading,
+                body: String::new(),
+            });
+        }
+        let chapter = chapters.last_mut().expect("a chapter has been started");
+        write_blocks(layout, &mut images, &mut chapter.body)?;
+    }
+    if chapters.is_empty() {
+        chapters.push(Chapter {
+            title: title.to_string(),
+            body: String::new(),
+        });
+    }
+    let cover = containers
+        .iter()
+        .flat_map(|container| container.photos())
+        .next()
+        .map(|photo| images.add(photo))
+        .transpose()?;
+
+    let mut modified = SystemTime::UNIX_EPOCH;
+    let mut identifier = Sha256::new();
+    identifier.update(title.as_bytes());
+    for (path, name) in &images.names {
+        identifier.update(name.as_bytes());
+        if let Ok(time) = fs::metadata(path).and_then(|metadata| metadata.modified()) {
+            modified = modified.max(time);
+        }
+    }
+    let hash = identifier.finalize();
+    let hex = hash[..16].iter().map(|b| format!("{:02x}", b)).join("");
+    // A name-based UUID, with the version and variant bits set
+    let variant = hash[8] & 0x3f | 0x80;
+    let uuid = format!(
+        "{}-{}-5{}-{:02x}{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[13..16],
+        variant,
+        &hex[18..20],
+        &hex[20..32]
+    );
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut nav = String::new();
+    let mut zip = ZipWriter::new();
+    // The mimetype must be the first file and must not be compressed
+    zip.add("mimetype", b"application/epub+zip", true)?;
+    zip.add(
+        "META-INF/container.xml",
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#,
+        false,
+    )?;
+    zip.add("OEBPS/style.css", STYLESHEET.as_bytes(), false)?;
+
+    let mut cover_body = format!("<h1>{}</h1>\n", escape(title));
+    if let Some(cover) = &cover {
+        cover_body = format!(
+            "<div class=\"cover\">\n{}<img src=\"{}\" alt=\"{}\"/>\n</div>\n",
+            cover_body,
+            escape(cover),
+            escape(title)
+        );
+    }
+    zip.add(
+        "OEBPS/cover.xhtml",
+        xhtml(title, &cover_body).as_bytes(),
+        false,
+    )?;
+    manifest.push_str(
+        "<item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+    );
+    spine.push_str("<itemref idref=\"cover\"/>\n");
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        let file = format!("chapter-{}.xhtml", i + 1);
+        zip.add(
+            &format!("OEBPS/{}", file),
+            xhtml(&chapter.title, &chapter.body).as_bytes(),
+            false,
+        )?;
+        let _ = writeln!(
+            manifest,
+            "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+            i + 1,
+            file
+        );
+        let _ = writeln!(spine, "<itemref idref=\"chapter-{}\"/>", i + 1);
+        let _ = writeln!(
+            nav,
+            "<li><a href=\"{}\">{}</a></li>",
+            file,
+            escape(&chapter.title)
+        );
+    }
+    let nav = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>\n",
+        escape(title),
+        nav
+    );
+    zip.add("OEBPS/nav.xhtml", xhtml(title, &nav).as_bytes(), false)?;
+    manifest.push_str(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    manifest.push_str("<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n");
+
+    for (i, (path, name)) in images.names.iter().enumerate() {
+        // Images are already compressed
+        zip.add(&format!("OEBPS/images/{}", name), &fs::read(path)?, true)?;
+        let href = format!("images/{}", name);
+        let properties = if cover.as_ref() == Some(&href) {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            manifest,
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"{}/>",
+            i + 1,
+            escape(&href),
+            Images::media_type(name),
+            properties
+        );
+    }
+
+    let package = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" xml:lang="{0}">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">urn:uuid:{1}</dc:identifier>
+<dc:title>{2}</dc:title>
+<dc:language>{0}</dc:language>
+<meta property="dcterms:modified">{3}</meta>
+</metadata>
+<manifest>
+{4}</manifest>
+<spine>
+{5}</spine>
+</package>
+"#,
+        LANGUAGE,
+        uuid,
+        escape(title),
+        format_utc(modified),
+        manifest,
+        spine
+    );
+    zip.add("OEBPS/content.opf", package.as_bytes(), false)?;
+    Album::write_if_changed(path, &zip.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: u64) -> String {
+        format_utc(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    #[test]
+    fn format_utc_dates() {
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(86399), "1970-01-01T23:59:59Z");
+        assert_eq!(at(951782400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(951868800), "2000-03-01T00:00:00Z");
+        assert_eq!(at(1700000000), "2023-11-14T22:13:20Z");
+        assert_eq!(at(4102444799), "2099-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn format_utc_before_epoch() {
+        let before = SystemTime::UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(format_utc(before), "1970-01-01T00:00:00Z");
+    }
+}
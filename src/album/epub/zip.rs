@@ -0,0 +1,225 @@
+use miniz_oxide::deflate::compress_to_vec;
+use std::io;
+use std::io::ErrorKind;
+
+/// Compression level of all compressed files, from 0 (none) to 10 (best).
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Modification date of all files in DOS format, which is 1980-01-01, so that the archive only
+/// changes if its content changes.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+/// Flag marking file names as UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// A file inside the archive, as it is listed in the central directory.
+struct Entry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    /// Position of the local header of the file inside the archive
+    offset: u32,
+}
+
+/// A minimal writer for ZIP archives, as used by EPUB files, which is written in memory.
+/// Files are either stored or compressed with deflate.
+pub struct ZipWriter {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        Self {
+            data: vec![],
+            entries: vec![],
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    /// Convert the given size or count into a field of the archive, failing if it is too large
+    /// for an archive without the ZIP64 extension.
+    fn field<T: TryFrom<usize>>(value: usize, what: &str) -> io::Result<T> {
+        T::try_from(value).map_err(|_| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("The EPUB archive is too large: {} is {}", what, value),
+            )
+        })
+    }
+
+    /// Add a file with the given name and content, compressed unless `store` is set.
+    /// Fails if the file or the archive get too large for the ZIP format.
+    pub fn add(&mut self, name: &str, content: &[u8], store: bool) -> io::Result<()> {
+        let (method, compressed) = if store {
+            (0, content.to_vec())
+        } else {
+            (8, compress_to_vec(content, COMPRESSION_LEVEL))
+        };
+        let entry = Entry {
+            name: name.to_string(),
+            method,
+            crc: crc32fast::hash(content),
+            compressed_size: Self::field(compressed.len(), "the compressed size")?,
+            size: Self::field(content.len(), "the size")?,
+            offset: Self::field(self.data.len(), "the offset")?,
+        };
+        let name_length = Self::field(name.len(), "the length of the name")?;
+        self.u32(0x04034b50);
+        self.u16(20);
+        self.u16(UTF8_NAMES);
+        self.u16(entry.method);
+        self.u16(0);
+        self.u16(DOS_DATE);
+        self.u32(entry.crc);
+        self.u32(entry.compressed_size);
+        self.u32(entry.size);
+        self.u16(name_length);
+        self.u16(0);
+        self.data.extend(name.as_bytes());
+        self.data.extend(compressed);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Write the central directory and return the complete archive.
+    /// Fails if the archive has too many files or gets too large for the ZIP format.
+    pub fn finish(mut self) -> io::Result<Vec<u8>> {
+        let directory = Self::field(self.data.len(), "the offset of the central directory")?;
+        let entries = std::mem::take(&mut self.entries);
+        let count = Self::field(entries.len(), "the number of files")?;
+        for entry in &entries {
+            self.u32(0x02014b50);
+            self.u16(20);
+            self.u16(20);
+            self.u16(UTF8_NAMES);
+            self.u16(entry.method);
+            self.u16(0);
+            self.u16(DOS_DATE);
+            self.u32(entry.crc);
+            self.u32(entry.compressed_size);
+            self.u32(entry.size);
+            // The length of the name has been checked when the file was added
+            self.u16(entry.name.len() as u16);
+            // Extra field, comment, disk, internal and external attributes
+            self.u16(0);
+            self.u16(0);
+            self.u16(0);
+            self.u16(0);
+            self.u32(0);
+            self.u32(entry.offset);
+            self.data.extend(entry.name.as_bytes());
+        }
+        let size = Self::field(
+            self.data.len() - directory as usize,
+            "the size of the central directory",
+        )?;
+        self.u32(0x06054b50);
+        self.u16(0);
+        self.u16(0);
+        self.u16(count);
+        self.u16(count);
+        self.u32(size);
+        self.u32(directory);
+        self.u16(0);
+        Ok(self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniz_oxide::inflate::decompress_to_vec;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([data[at], data[at + 1]])
+    }
+
+    fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    #[test]
+    fn round_trip() {
+        let files: [(&str, &[u8], bool); 3] = [
+            ("mimetype", b"application/epub+zip", true),
+            (
+                "OEBPS/page.xhtml",
+                b"<p>Hello, hello, hello, hello!</p>",
+                false,
+            ),
+            (
+                "OEBPS/images/Łódź.jpg",
+                &[0xff, 0xd8, 0x00, 0xff, 0xd9],
+                true,
+            ),
+        ];
+        let mut zip = ZipWriter::new();
+        for (name, content, store) in files {
+            zip.add(name, content, store).unwrap();
+        }
+        let data = zip.finish().unwrap();
+
+        let end = data.len() - 22;
+        assert_eq!(u32_at(&data, end), 0x06054b50);
+        assert_eq!(u16_at(&data, end + 8) as usize, files.len());
+        assert_eq!(u16_at(&data, end + 10) as usize, files.len());
+        let directory = u32_at(&data, end + 16) as usize;
+        assert_eq!(directory + u32_at(&data, end + 12) as usize, end);
+
+        let mut at = directory;
+        for (name, content, store) in files {
+            assert_eq!(u32_at(&data, at), 0x02014b50);
+            let method = u16_at(&data, at + 10);
+            let crc = u32_at(&data, at + 16);
+            let compressed_size = u32_at(&data, at + 20) as usize;
+            let name_length = u16_at(&data, at + 28) as usize;
+            let offset = u32_at(&data, at + 42) as usize;
+            assert_eq!(&data[at + 46..at + 46 + name_length], name.as_bytes());
+            assert_eq!(method, if store { 0 } else { 8 });
+            assert_eq!(crc, crc32fast::hash(content));
+            assert_eq!(u32_at(&data, at + 24) as usize, content.len());
+
+            // The local header repeats the fields of the central directory
+            assert_eq!(u32_at(&data, offset), 0x04034b50);
+            assert_eq!(u16_at(&data, offset + 8), method);
+            assert_eq!(u32_at(&data, offset + 14), crc);
+            assert_eq!(u16_at(&data, offset + 26) as usize, name_length);
+            let start = offset + 30 + name_length;
+            let stored = &data[start..start + compressed_size];
+            let unpacked = if store {
+                stored.to_vec()
+            } else {
+                decompress_to_vec(stored).unwrap()
+            };
+            assert_eq!(unpacked, content);
+            at += 46 + name_length;
+        }
+        assert_eq!(at, end);
+    }
+
+    #[test]
+    fn mimetype_comes_first() {
+        let mut zip = ZipWriter::new();
+        zip.add("mimetype", b"application/epub+zip", true).unwrap();
+        let data = zip.finish().unwrap();
+        assert_eq!(&data[30..38], b"mimetype");
+        assert_eq!(&data[38..58], b"application/epub+zip");
+    }
+
+    #[test]
+    fn too_long_name() {
+        let mut zip = ZipWriter::new();
+        let name = "a".repeat(usize::from(u16::MAX) + 1);
+        assert!(zip.add(&name, b"", true).is_err());
+    }
+}
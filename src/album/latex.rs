@@ -0,0 +1,178 @@
+use crate::album::assets::AssetFile;
+use crate::album::pdf::{PrintBlock, PrintLayout};
+use crate::album::photo::PhotoContainer;
+use crate::album::Album;
+use itertools::Itertools;
+use std::fmt::Write;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// File name of the default document class among the assets.
+pub const DEFAULT_CLASS: &str = "radalbum.cls";
+
+/// Space between two photos of a row, as fraction of the line width.
+const GAP: f32 = 0.02;
+
+/// Maximum height of a photo, as fraction of the text height.
+const MAX_PHOTO_HEIGHT: f32 = 0.7;
+
+/// Escape text for use inside a LaTeX document.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '%' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '<' => escaped.push_str("\\textless{}"),
+            '>' => escaped.push_str("\\textgreater{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Characters that LaTeX does not keep as they are inside the path of `\includegraphics`.
+const PATH_SPECIAL_CHARS: &[char] = &['{', '}', '%', '#', '~', '$', '^', '&', '\\'];
+
+/// Prepare a path for use inside `\includegraphics`, which must keep the path as it is.
+/// Paths with characters that LaTeX would interpret are refused, as they cannot be escaped
+/// there.
+fn escape_path(path: &Path) -> io::Result<String> {
+    let path = path
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+    match path.chars().find(|c| PATH_SPECIAL_CHARS.contains(c)) {
+        Some(c) => Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The photo {} cannot be used in the LaTeX album, because its name contains {}. \
+                 Please rename it, or write the photos with --filenames slug.",
+                path, c
+            ),
+        )),
+        None => Ok(path),
+    }
+}
+
+/// Write the blocks of a photo container. The route and the statistics of a chapter are put
+/// into a box, and the photos together with their title and caption into one figure, so that
+/// the caption is never separated from its photos.
+fn write_container(layout: &PrintLayout, out: &mut String) -> io::Result<()> {
+    // Writing into a string cannot fail
+    let mut details = vec![];
+    let mut figure = String::new();
+    for block in &layout.blocks {
+        match block {
+            PrintBlock::Heading(text) if layout.chapter => {
+                let _ = writeln!(out, "\\chapter{{{}}}", escape(text));
+            },
+            PrintBlock::Heading(text) => {
+                let _ = writeln!(out, "\\section*{{{}}}", escape(text));
+            },
+            PrintBlock::Details(text) => details.push(escape(text)),
+            PrintBlock::Row(photos) if photos.is_empty() => {},
+            PrintBlock::Row(photos) => {
+                let count = photos.len() as f32;
+                let width = (1.0 - GAP * (count - 1.0)) / count;
+                let row = photos
+                    .iter()
+                    .map(|photo| {
+                        Ok(format!(
+                            "\\includegraphics[width={:.3}\\linewidth,height={}\\textheight,\
+                             keepaspectratio]{{{}}}",
+                            width,
+                            MAX_PHOTO_HEIGHT,
+                            escape_path(&photo.get_relative_path())?
+                        ))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?
+                    .join("\n\\photogap\n");
+                let _ = writeln!(figure, "{}\\par\\medskip", row);
+            },
+            PrintBlock::Title(text) => {
+                let _ = writeln!(figure, "\\phototitle{{{}}}", escape(text));
+            },
+            PrintBlock::Caption { text, .. } => {
+                let text = text.lines().map(escape).join("\\\\\n");
+                let _ = writeln!(figure, "\\photocaption{{{}}}", text);
+            },
+        }
+    }
+    if !details.is_empty() {
+        let _ = writeln!(
+            out,
+            "\\begin{{ridestats}}\n{}\n\\end{{ridestats}}",
+            details.join("\\\\\n")
+        );
+    }
+    if !figure.is_empty() {
+        let _ = writeln!(
+            out,
+            "\\begin{{figure}}[H]\n\\centering\n{}\\end{{figure}}",
+            figure
+        );
+    }
+    out.push('\n');
+    Ok(())
+}
+
+/// Write the given containers as LaTeX document to the given path, using the given document
+/// class, which is written next to the document.
+/// Every day starts a new chapter. The files are only rewritten if their content changed.
+pub fn write_latex(
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    class: &AssetFile,
+    path: &Path,
+) -> io::Result<()> {
+    let Some(class_name) = Path::new(&class.name).file_stem() else {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid LaTeX class: {}", class.name),
+        ));
+    };
+    Album::write_if_changed(&path.with_file_name(&class.name), &class.content)?;
+    let mut document = format!(
+        "\\documentclass{{{}}}\n\n\\title{{{}}}\n\\date{{}}\n\n\\begin{{document}}\n\\maketitle\n\n",
+        class_name.to_string_lossy(),
+        escape(title)
+    );
+    for container in containers {
+        write_container(&container.print_layout(), &mut document)?;
+    }
+    document.push_str("\\end{document}\n");
+    Album::write_if_changed(path, document.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_special_characters() {
+        assert_eq!(escape("Tag 3: Łódź"), "Tag 3: Łódź");
+        assert_eq!(escape("50% & #1 for $5"), "50\\% \\& \\#1 for \\$5");
+        assert_eq!(escape("{a_b}"), "\\{a\\_b\\}");
+        assert_eq!(
+            escape("~^\\<>"),
+            "\\textasciitilde{}\\textasciicircum{}\\textbackslash{}\\textless{}\\textgreater{}"
+        );
+    }
+
+    #[test]
+    fn escape_paths() {
+        assert_eq!(
+            escape_path(Path::new("img/Straße in Łódź.jpg")).unwrap(),
+            "img/Straße in Łódź.jpg"
+        );
+        for name in ["img/50%.jpg", "img/#1.jpg", "img/a~b.jpg", "img/{a}.jpg"] {
+            assert!(escape_path(Path::new(name)).is_err(), "{}", name);
+        }
+    }
+}
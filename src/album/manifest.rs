@@ -0,0 +1,137 @@
+use crate::album::photo::PhotoContainer;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::ops::Not;
+use std::path::Path;
+use std::{fs, io};
+
+/// Version of the manifest format. Manifests with another version are rejected.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Explanation at the beginning of every written manifest.
+const HEADER: &str = "\
+# Album manifest written by radalbum. The album is built from this file instead of the titles of
+# the photos, so the containers can be reordered, regrouped, re-captioned or excluded here.
+# Every [[container]] shows its photos, given by their file names in the input directory, next
+# to each other (at most four). `day = true` makes a single photo a day header, whose caption
+# holds the fields of the day. `title` and `caption` replace those of the last photo, and
+# `exclude = true` leaves the photos out of the album. Photos that are not listed yet are added
+# at the end, marked with `new = true`.
+
+";
+
+/// Comment in front of the photos added to an existing manifest.
+const ADDED: &str = "\n# Photos added since the manifest was written\n";
+
+/// The containers of the album, in the order they are shown.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    #[serde(default, rename = "container")]
+    pub containers: Vec<ManifestEntry>,
+}
+
+/// One container of the album manifest.
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// File names of the photos inside the input directory, in the order they are shown
+    pub photos: Vec<String>,
+    /// Whether the photo is a day header
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub day: bool,
+    /// Title of the container, replacing the title of its last photo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Caption of the container, replacing the caption of its last photo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Whether the photos are left out of the album
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub exclude: bool,
+    /// Whether the photos have been added to an existing manifest, and still need to be placed
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub new: bool,
+}
+
+impl ManifestEntry {
+    /// The entry of the given container, with the title and the caption of its last photo.
+    fn of(container: &dyn PhotoContainer, new: bool) -> Self {
+        let photos = container.photos().collect::<Vec<_>>();
+        let last = photos.last();
+        Self {
+            photos: photos
+                .iter()
+                .filter_map(|photo| photo.get_path().file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect(),
+            day: container.day_summary().is_some(),
+            title: last
+                .map(|photo| photo.get_html_escaped_title())
+                .filter(|title| !title.is_empty()),
+            caption: last
+                .map(|photo| photo.get_html_escaped_caption())
+                .filter(|caption| !caption.is_empty()),
+            exclude: false,
+            new,
+        }
+    }
+}
+
+/// The containers of a manifest, without its version, to append them to an existing manifest.
+#[derive(Serialize)]
+struct Entries {
+    #[serde(rename = "container")]
+    containers: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Load the manifest at the given path.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let manifest = toml::from_str::<Self>(&fs::read_to_string(path)?).map_err(|e| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid manifest {}: {}", path.display(), e),
+            )
+        })?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Manifest {} has version {}, but only version {} is supported",
+                    path.display(),
+                    manifest.version,
+                    MANIFEST_VERSION
+                ),
+            ));
+        }
+        Ok(manifest)
+    }
+
+    /// Write a new manifest with the given containers to the given path.
+    pub fn write(path: &Path, containers: &[Box<dyn PhotoContainer>]) -> io::Result<()> {
+        let manifest = Self {
+            version: MANIFEST_VERSION,
+            containers: containers
+                .iter()
+                .map(|container| ManifestEntry::of(container.as_ref(), false))
+                .collect(),
+        };
+        let content = toml::to_string(&manifest).map_err(io::Error::other)?;
+        fs::write(path, format!("{}{}", HEADER, content))
+    }
+
+    /// Append the given containers to the end of the existing manifest at the given path, marked
+    /// as new. The rest of the manifest is kept as it is, including its comments.
+    pub fn append(path: &Path, containers: &[Box<dyn PhotoContainer>]) -> io::Result<()> {
+        let entries = Entries {
+            containers: containers
+                .iter()
+                .map(|container| ManifestEntry::of(container.as_ref(), true))
+                .collect(),
+        };
+        let content = toml::to_string(&entries).map_err(io::Error::other)?;
+        let mut file = OpenOptions::new().append(true).open(path)?;
+        write!(file, "{}{}", ADDED, content)
+    }
+}
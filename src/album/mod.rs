@@ -1,49 +1,100 @@
+pub mod assets;
+pub mod config;
+pub mod epub;
+pub mod latex;
+pub mod manifest;
+pub mod model;
+pub mod pdf;
 pub mod photo;
+pub mod templates;
+pub mod theme;
+pub mod typst;
+pub mod website;
 
+use crate::album::assets::Assets;
+use crate::album::manifest::Manifest;
+use crate::album::model::AlbumModel;
+use crate::album::photo::build_cache::BuildCache;
+use crate::album::photo::export_target::{ExportTarget, TargetKind};
+use crate::album::photo::output_registry::OutputRegistry;
 use crate::album::photo::FourPhotosTwoByTwo::FourPhotosTwoByTwo;
 use crate::album::photo::SinglePhoto::SinglePhoto;
 use crate::album::photo::TagMarker::TagMarker;
 use crate::album::photo::ThreePhotos::ThreePhotos;
 use crate::album::photo::TwoPhotos::TwoPhotos;
-use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::photo::{FilenameStyle, Photo, PhotoContainer};
+use crate::album::templates::Templates;
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufWriter, ErrorKind, Write};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::{env, fs, io, mem};
+use std::{fs, io, mem};
 
 pub struct Album {
+    /// Title of the album, printed at its beginning
+    title: String,
     photos: Vec<Photo>,
     collected_photos: Option<Vec<Box<dyn PhotoContainer>>>,
 }
 
 impl Album {
-    const ASSETS_DIR: &'static str = "assets";
-    pub fn import_all_photos(path: &PathBuf) -> Result<Self, io::Error> {
+    /// Title of albums whose input directory has no name
+    const DEFAULT_TITLE: &'static str = "Album";
+    /// File name of the stylesheet used by the HTML and PDF album
+    pub const STYLESHEET: &'static str = "Album.css";
+    /// Import all photos from the given directory, into an album titled with the name of the
+    /// directory.
+    /// The metadata of photos that did not change since the last run is taken from the cache,
+    /// the metadata of all other photos is read in parallel.
+    /// Photos that cannot be loaded are left out, or fail the import if `strict` is set.
+    pub fn import_all_photos(
+        path: &PathBuf,
+        cache: &mut BuildCache,
+        strict: bool,
+    ) -> Result<Self, io::Error> {
+        let sources = fs::read_dir(path)?
+            .filter_map(|p| p.ok())
+            .map(|p| p.path())
+            .filter_map(|p| {
+                if p.extension()
+                    .map_or(false, |ext| ext == "jpg" || ext == "png")
+                {
+                    Some(p)
+                } else {
+                    None
+                }
+            })
+            .sorted()
+            .collect::<Vec<_>>();
+        let title = path
+            .canonicalize()
+            .unwrap_or_else(|_| path.clone())
+            .file_name()
+            .map_or(Self::DEFAULT_TITLE.to_string(), |name| {
+                name.to_string_lossy().into_owned()
+            });
         Ok(Album {
+            title,
             collected_photos: None,
-            photos: fs::read_dir(path)?
-                .filter_map(|p| p.ok())
-                .map(|p| p.path())
-                .filter_map(|p| {
-                    if p.extension()
-                        .map_or(false, |ext| ext == "jpg" || ext == "png")
-                    {
-                        Some(p)
-                    } else {
-                        None
-                    }
+            photos: cache
+                .load_photos(sources)
+                .into_iter()
+                .filter_map(|f| match f {
+                    Ok(photo) => Some(Ok(photo)),
+                    Err(e) if strict => Some(Err(io::Error::other(e.to_string()))),
+                    Err(_) => None,
                 })
-                .sorted()
-                .map(|p| {
-                    println!("Loading Photo {:?}", &p);
-                    p
-                })
-                .map(Photo::load_from_disk)
-                .filter_map(|f| f.ok())
-                .collect::<Vec<_>>(),
+                .collect::<Result<Vec<_>, _>>()?,
         })
     }
+
+    /// Use the given title instead of the name of the input directory.
+    pub fn with_title(mut self, title: String) -> Self {
+        self.title = title;
+        self
+    }
+
     fn is_tag_marker(photo: &Photo) -> bool {
         if photo
             .get_html_escaped_caption()
@@ -72,105 +123,591 @@ impl Album {
         false
     }
 
+    /// The container of the given photos, in the order they are shown. A single photo is a day
+    /// header if `day` is set, which gets an anchor that is unique among the given anchors.
+    fn container(
+        photos: Vec<Photo>,
+        day: bool,
+        anchors: &mut HashSet<String>,
+    ) -> Option<Box<dyn PhotoContainer>> {
+        let count = photos.len();
+        let mut photos = photos.into_iter();
+        let mut next = || photos.next().unwrap();
+        Some(match count {
+            1 if day => {
+                let marker = TagMarker::new(next());
+                let anchor = match marker.anchor() {
+                    "" => "day",
+                    anchor => anchor,
+                };
+                let anchor = website::unique_slug(anchors, anchor);
+                Box::new(marker.with_anchor(anchor))
+            },
+            // Single Full-Size Photo
+            1 => Box::new(SinglePhoto::new(next())),
+            2 => Box::new(TwoPhotos::new(next(), next())),
+            3 => Box::new(ThreePhotos::new(next(), next(), next())),
+            4 => Box::new(FourPhotosTwoByTwo::new(next(), next(), next(), next())),
+            _ => {
+                eprintln!("Unsupported Image Count: {}", count);
+                return None;
+            },
+        })
+    }
+
+    /// Group the given photos into containers by their titles. Photos titled `/` are shown
+    /// together with the next photo that has another title, which holds the title and the
+    /// caption of the container.
+    fn group_photos(
+        photos: Vec<Photo>,
+        anchors: &mut HashSet<String>,
+    ) -> Vec<Box<dyn PhotoContainer>> {
+        let mut containers = vec![];
+        let mut stack: Vec<Photo> = vec![];
+        for photo in photos.into_iter() {
+            if photo.get_html_escaped_title().trim() == "/" {
+                stack.push(photo);
+            } else if stack.len() > 3 {
+                eprintln!("Unsupported Image Count: {}", stack.len());
+            } else {
+                let day = stack.is_empty() && Self::is_tag_marker(&photo);
+                // The grouped photos are shown in reverse order
+                let mut group = stack.drain(..).rev().collect::<Vec<_>>();
+                group.push(photo);
+                containers.extend(Self::container(group, day, anchors));
+            }
+        }
+        containers
+    }
+
     /// Collect all photos into their appropriate containers.
     /// This will move all photos and empty the photos vector
     pub fn collect_photos(&mut self) -> () {
         if self.collected_photos.is_none() {
             self.collected_photos = Some(vec![]);
         }
-        let mut stack: Vec<Photo> = vec![];
+        // Every day header gets an anchor that is unique inside the album
+        let mut anchors = HashSet::new();
         let photos = mem::replace(&mut self.photos, vec![]);
-        for photo in photos.into_iter() {
-            if photo.get_html_escaped_title().trim() == "/" {
-                stack.push(photo);
-            } else {
-                match stack.len() {
-                    // Single Full-Size Photo or tag marker
-                    0 => self.collected_photos.as_mut().unwrap().push(
-                        if Self::is_tag_marker(&photo) {
-                            Box::new(TagMarker::new(photo))
-                        } else {
-                            Box::new(SinglePhoto::new(photo))
-                        },
-                    ),
-                    1 => self
-                        .collected_photos
-                        .as_mut()
-                        .unwrap()
-                        .push(Box::new(TwoPhotos::new(stack.pop().unwrap(), photo))),
-                    2 => self
-                        .collected_photos
-                        .as_mut()
-                        .unwrap()
-                        .push(Box::new(ThreePhotos::new(
-                            stack.pop().unwrap(),
-                            stack.pop().unwrap(),
-                            photo,
-                        ))),
-                    3 => self.collected_photos.as_mut().unwrap().push(Box::new(
-                        FourPhotosTwoByTwo::new(
-                            stack.pop().unwrap(),
-                            stack.pop().unwrap(),
-                            stack.pop().unwrap(),
-                            photo,
-                        ),
-                    )),
-                    _ => eprintln!("Unsupported Image Count: {}", stack.len()),
+        let containers = Self::group_photos(photos, &mut anchors);
+        self.collected_photos.as_mut().unwrap().extend(containers);
+    }
+
+    /// Collect all photos into the containers given by the manifest at the given path, instead
+    /// of grouping them by their titles.
+    /// If the manifest does not exist yet, the photos are grouped by their titles and the
+    /// manifest is written with these containers, to be edited for the next build.
+    /// Photos that are not in the manifest are grouped by their titles and added at the end of
+    /// the album, and at the end of the manifest, marked as new. Photos of the manifest that do
+    /// not exist anymore are left out.
+    pub fn apply_manifest(&mut self, path: &Path) -> io::Result<()> {
+        if !path.exists() {
+            self.collect_photos();
+            println!("Writing the album manifest {}", path.display());
+            let containers = self.collected_photos.as_deref().unwrap_or_default();
+            return Manifest::write(path, containers);
+        }
+        let manifest = Manifest::load(path)?;
+        let mut photos = mem::take(&mut self.photos)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        let index = photos
+            .iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(i, photo)| {
+                let name = photo.get_path().file_name()?;
+                Some((name.to_string_lossy().into_owned(), i))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut anchors = HashSet::new();
+        let mut containers = vec![];
+        for entry in manifest.containers {
+            let mut group = vec![];
+            for name in &entry.photos {
+                match index.get(name).map(|&i| photos[i].take()) {
+                    Some(Some(photo)) => group.push(photo),
+                    Some(None) => {
+                        eprintln!("Photo {} is used more than once in the manifest", name)
+                    },
+                    None => eprintln!("Photo {} of the manifest does not exist", name),
                 }
             }
+            if entry.exclude || group.is_empty() {
+                continue;
+            }
+            if let Some(last) = group.pop() {
+                group.push(last.with_text(entry.title.as_deref(), entry.caption.as_deref()));
+            }
+            if entry.day && group.len() != 1 {
+                eprintln!("A day header needs exactly one photo: {:?}", entry.photos);
+            }
+            containers.extend(Self::container(group, entry.day, &mut anchors));
+        }
+        let added = Self::group_photos(photos.into_iter().flatten().collect(), &mut anchors);
+        if !added.is_empty() {
+            eprintln!(
+                "Adding {} new containers at the end of the album and of the manifest {}",
+                added.len(),
+                path.display()
+            );
+            Manifest::append(path, &added)?;
+            containers.extend(added);
+        }
+        self.collected_photos = Some(containers);
+        Ok(())
+    }
+
+    /// Write the model of the album, with the photos grouped into containers, as JSON to the
+    /// given path. The photos are referenced by their source paths.
+    pub fn write_model(&mut self, path: &Path) -> io::Result<()> {
+        self.collect_photos();
+        let containers = self.collected_photos.as_deref().unwrap_or_default();
+        AlbumModel::new(containers, &self.title).write_json(path)
+    }
+
+    /// Write the stylesheet with the theme of the given assets into the given output directory,
+    /// and copy the files of the theme.
+    pub fn write_aux_files(path: &Path, assets: &Assets) -> io::Result<()> {
+        let theme = assets.theme();
+        Self::write_if_changed(&path.join(Self::STYLESHEET), &theme.stylesheet(assets)?)?;
+        theme.write_files(path)
+    }
+
+    /// Write the given content into the given file, unless the file already has this content.
+    /// Unchanged files keep their modification time, so no document is rebuilt from them.
+    fn write_if_changed(path: &Path, content: &[u8]) -> io::Result<()> {
+        if fs::read(path).is_ok_and(|existing| existing == content) {
+            return Ok(());
         }
+        fs::write(path, content)
     }
-    fn write_aux_files(&self, path: &Path) -> io::Result<()> {
-        let mut assets_path = env::current_exe()?
-            .parent()
-            .ok_or(io::Error::new(
-                ErrorKind::Unsupported,
-                "Exe Path did not have a parent!",
-            ))?
-            .join(Self::ASSETS_DIR);
-        if !assets_path.is_dir() {
-            assets_path = PathBuf::from(Self::ASSETS_DIR);
+
+    /// Write the table of contents of the given containers, with a link to every day, its route
+    /// and its distance. Nothing is written if there are no days.
+    /// The HTML is written without blank lines, so that it stays one raw HTML block.
+    fn print_contents(containers: &[Box<dyn PhotoContainer>], f: &mut dyn Write) -> io::Result<()> {
+        let days = containers
+            .iter()
+            .filter_map(|container| container.day_summary())
+            .collect::<Vec<_>>();
+        if days.is_empty() {
+            return Ok(());
         }
-        for asset in ["Makefile", "Album.css"] {
-            fs::copy(assets_path.join(asset), path.join(asset))?;
+        writeln!(f, "<nav class=\"toc\">")?;
+        writeln!(f, "<ol>")?;
+        for day in days {
+            write!(
+                f,
+                "<li><a href=\"#{}\">{}</a>",
+                website::escape(day.anchor),
+                website::escape(day.title)
+            )?;
+            if let Some(route) = day.route() {
+                write!(
+                    f,
+                    " <span class=\"route\">{}</span>",
+                    website::escape(&route)
+                )?;
+            }
+            if let Some(distance) = day.distance {
+                write!(
+                    f,
+                    " <span class=\"distance\">{}</span>",
+                    website::escape(distance)
+                )?;
+            }
+            writeln!(f, "</li>")?;
         }
+        writeln!(f, "</ol>")?;
+        writeln!(f, "</nav>")?;
+        writeln!(f)?;
         Ok(())
     }
 
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> io::Result<()> {
-        write!(f, "# Test-Album\n\n")?;
-        for container in self
-            .collected_photos
-            .as_ref()
-            .expect("Please call collect_photos before calling this function!")
-        {
-            container.print_markdown(f)?;
+    /// Write the given containers as Markdown album with the given title, rendered with the given
+    /// templates.
+    fn print_markdown(
+        title: &str,
+        containers: &[Box<dyn PhotoContainer>],
+        table_of_contents: bool,
+        templates: &Templates,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut contents: Vec<u8> = vec![];
+        if table_of_contents {
+            Self::print_contents(containers, &mut contents)?;
         }
-        Ok(())
+        let containers = containers
+            .iter()
+            .enumerate()
+            .map(|(index, container)| {
+                let mut markdown: Vec<u8> = vec![];
+                container.print_markdown(templates, index, &mut markdown)?;
+                Ok(String::from_utf8_lossy(&markdown).into_owned())
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        templates.render_album(
+            title,
+            &String::from_utf8_lossy(&contents),
+            &containers,
+            f,
+        )
     }
-    /// Write the complete album to disk as Markdown file and copy all photos
-    pub fn write_to_disk(&mut self, path: &Path) {
-        if let Err(e) = self.write_aux_files(path) {
+    /// Write the complete album to disk and copy all photos, together with the stylesheet and the
+    /// theme of the given assets.
+    /// For every export target, the photos are processed for that target and a Markdown file
+    /// referencing the processed photos is written with the templates of the given assets,
+    /// starting with a table of contents if the target has one.
+    /// The file names of the written photos are chosen according to the given filename style.
+    /// Photos that have already been written by an earlier run are taken from the given cache,
+    /// and Markdown files are only rewritten if their content changed.
+    /// The photos of all targets are resized and written in parallel.
+    /// Targets with native PDF settings also get their PDF album laid out and written, targets
+    /// with a LaTeX class or a Typst template their LaTeX or Typst album, targets with the
+    /// website enabled their website, and the EPUB target gets its EPUB album written.
+    /// Photos that cannot be written are left out of the album. If `strict` is set, the album
+    /// fails instead, and the photos, the Markdown files and the build cache of the previous run
+    /// are kept.
+    pub fn write_to_disk(
+        &mut self,
+        path: &Path,
+        filename_style: FilenameStyle,
+        targets: &[ExportTarget],
+        assets: &Assets,
+        cache: BuildCache,
+        strict: bool,
+    ) -> io::Result<()> {
+        if let Err(e) = Self::write_aux_files(path, assets) {
+            if strict {
+                return Err(e);
+            }
+            eprintln!("{:?}", e);
+        }
+        let templates = Templates::load(assets)?;
+        self.collect_photos();
+        let collected_photos = self.collected_photos.as_ref().unwrap();
+        let mut registry = OutputRegistry::new(filename_style, cache);
+        let sources = collected_photos
+            .iter()
+            .flat_map(|container| container.photos())
+            .flat_map(|photo| photo.get_files())
+            .collect::<Vec<_>>();
+        if let Err(e) = registry.hash_sources(&sources) {
             eprintln!("{:?}", e);
         }
-        // Copy all photos to the output directory.
-        for photo in &mut self.photos {
-            match photo.write_to_directory(path) {
-                Ok(p) => {
-                    *photo = p;
-                },
-                Err(e) => {
-                    eprintln!("{:?}", e);
-                },
+        // Decide the output paths of all photos of all targets first, so that the file names do
+        // not depend on the order the photos are written in.
+        let mut written_targets = vec![];
+        let mut errors = 0;
+        for target in targets {
+            let containers = collected_photos
+                .iter()
+                .filter_map(|container| {
+                    match container.write_to_directory(path, target, &mut registry) {
+                        Ok(written) => Some((container, written)),
+                        Err(e) => {
+                            eprintln!(
+                                "Leaving out the {:?} container of {}: {:?}",
+                                container.model().kind,
+                                Self::describe_photos(container.photos()),
+                                e
+                            );
+                            errors += 1;
+                            None
+                        },
+                    }
+                })
+                .collect::<Vec<_>>();
+            written_targets.push((target, containers));
+        }
+        // Write all photos in parallel, and leave out the containers of photos that failed
+        let failed = registry
+            .write_pending()
+            .into_iter()
+            .map(|(out_path, e)| {
+                eprintln!("{:?}: {:?}", out_path, e);
+                out_path
+            })
+            .collect::<HashSet<_>>();
+        errors += failed.len();
+        if strict && errors > 0 {
+            registry.discard_written();
+            return Err(io::Error::other(format!(
+                "{} photos could not be written",
+                errors
+            )));
+        }
+        registry.commit_written()?;
+        for (target, containers) in written_targets {
+            let containers = containers
+                .into_iter()
+                .filter_map(|(container, written)| {
+                    let missing = container
+                        .photos()
+                        .zip(written.photos())
+                        .filter(|(_, photo)| photo.get_files().any(|file| failed.contains(file)))
+                        .map(|(photo, _)| photo)
+                        .collect::<Vec<_>>();
+                    if missing.is_empty() {
+                        return Some(written);
+                    }
+                    eprintln!(
+                        "Leaving out the {:?} container of {}, because {} could not be written",
+                        container.model().kind,
+                        Self::describe_photos(container.photos()),
+                        Self::describe_photos(missing.into_iter())
+                    );
+                    None
+                })
+                .collect::<Vec<_>>();
+            // Write into a temporary file first, to keep an unchanged Markdown file untouched
+            let markdown_path = path.join(target.markdown_file);
+            let new_path = markdown_path.with_extension("md.new");
+            let out = File::create(&new_path)?;
+            let mut out: Box<dyn Write> = Box::new(BufWriter::new(out));
+            Self::print_markdown(
+                &self.title,
+                &containers,
+                target.table_of_contents,
+                &templates,
+                &mut out,
+            )?;
+            out.flush()?;
+            drop(out);
+            Self::write_if_changed(&markdown_path, &fs::read(&new_path)?)?;
+            fs::remove_file(&new_path)?;
+            if let Some(settings) = &target.pdf {
+                pdf::write_pdf(
+                    &containers,
+                    &self.title,
+                    settings,
+                    target.table_of_contents,
+                    &path.join("Album.pdf"),
+                )?;
+            }
+            if let Some(class) = &target.latex_class {
+                latex::write_latex(&containers, &self.title, class, &path.join("Album.tex"))?;
+            }
+            if let Some(template) = &target.typst_template {
+                typst::write_typst(&containers, &self.title, template, &path.join("Album.typ"))?;
+            }
+            if target.website {
+                for page in website::write_website(&containers, &self.title, &templates, path)? {
+                    registry.record_document(&page);
+                }
+            }
+            if target.kind == TargetKind::Ebook {
+                epub::write_epub(&containers, &self.title, &path.join("Album.epub"))?;
             }
         }
-        self.collect_photos();
-        let out = File::create(path.join("Album.md")).unwrap();
-        let mut out = BufWriter::new(out);
-        let mut outBoxed: Box<dyn Write> = Box::new(out);
-        self.print_markdown(&mut outBoxed).unwrap();
-        for photo in &self.photos {
-            println!("{}", photo)
+        if let Err(e) = registry.finish() {
+            eprintln!("Could not save the build cache: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// The source paths of the given photos, for error messages.
+    fn describe_photos<'a>(photos: impl Iterator<Item = &'a Photo>) -> String {
+        photos
+            .map(|photo| photo.get_path().display().to_string())
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::album::manifest::Manifest;
+
+    /// An album of the given photos, given by their title and caption.
+    fn album(photos: &[(&str, &str)]) -> Album {
+        Album {
+            title: "Album".to_string(),
+            photos: photos
+                .iter()
+                .enumerate()
+                .map(|(i, (title, caption))| {
+                    Photo::for_test(&format!("input/{}.jpg", i), title, caption)
+                })
+                .collect(),
+            collected_photos: None,
+        }
+    }
+
+    /// An album of photos with the given file names, titled by their names.
+    fn named_album(names: &[&str]) -> Album {
+        Album {
+            title: "Album".to_string(),
+            photos: names
+                .iter()
+                .map(|name| Photo::for_test(&format!("input/{}", name), name, ""))
+                .collect(),
+            collected_photos: None,
         }
     }
+
+    #[test]
+    fn day_headers_get_unique_anchors() {
+        let mut album = album(&[
+            ("Tag 1: Łódź", ""),
+            ("A photo", ""),
+            ("Tag 1: Łódź", ""),
+            ("", "Distance: 12 km"),
+            ("Day", ""),
+        ]);
+        album.collect_photos();
+        let anchors = album
+            .collected_photos
+            .as_ref()
+            .unwrap()
+            .iter()
+            .filter_map(|container| container.day_summary())
+            .map(|day| day.anchor.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(anchors, ["tag-1-lodz", "tag-1-lodz-2", "day", "day-2"]);
+    }
+
+    #[test]
+    fn contents_link_to_the_anchors() {
+        let mut album = album(&[
+            ("Tag 1", "From: Berlin\nTo: Łódź\nDistance: 12 km"),
+            ("Tag 1", ""),
+        ]);
+        album.collect_photos();
+        let mut out = vec![];
+        Album::print_contents(album.collected_photos.as_ref().unwrap(), &mut out).unwrap();
+        let contents = String::from_utf8(out).unwrap();
+        assert!(contents.contains(
+            "<li><a href=\"#tag-1\">Tag 1</a> <span class=\"route\">Berlin → Łódź</span> \
+             <span class=\"distance\">12</span></li>"
+        ));
+        assert!(contents.contains("<li><a href=\"#tag-1-2\">Tag 1</a></li>"));
+        // A blank line would end the raw HTML block
+        assert!(!contents.trim_end().contains("\n\n"));
+
+        let mut out = vec![];
+        Album::print_contents(&[], &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    /// The path of a manifest inside a new temporary directory of the given test.
+    fn manifest_path(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("radalbum-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory.join("manifest.toml")
+    }
+
+    /// The file names of the photos of all collected containers.
+    fn containers(album: &Album) -> Vec<Vec<String>> {
+        album
+            .collected_photos
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|container| {
+                container
+                    .photos()
+                    .map(|photo| {
+                        photo
+                            .get_path()
+                            .file_name()
+                            .unwrap()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn manifest_is_written_if_missing() {
+        let path = manifest_path("manifest-missing");
+        let mut album = named_album(&["a.jpg", "b.jpg"]);
+        album.apply_manifest(&path).unwrap();
+        assert_eq!(containers(&album), [["a.jpg"], ["b.jpg"]]);
+        let manifest = Manifest::load(&path).unwrap();
+        let photos = manifest
+            .containers
+            .iter()
+            .map(|entry| entry.photos.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(photos, [["a.jpg"], ["b.jpg"]]);
+        assert!(manifest.containers.iter().all(|entry| !entry.new));
+        assert_eq!(manifest.containers[0].title.as_deref(), Some("a.jpg"));
+    }
+
+    #[test]
+    fn manifest_groups_excludes_and_adds_photos() {
+        let path = manifest_path("manifest-merge");
+        fs::write(
+            &path,
+            r#"version = 1
+
+[[container]]
+photos = ["b.jpg", "a.jpg"]
+title = "Pair"
+caption = "Both"
+
+[[container]]
+photos = ["c.jpg"]
+exclude = true
+
+[[container]]
+photos = ["missing.jpg", "a.jpg"]
+"#,
+        )
+        .unwrap();
+        let mut first = named_album(&["a.jpg", "b.jpg", "c.jpg", "d.jpg"]);
+        first.apply_manifest(&path).unwrap();
+        assert_eq!(
+            containers(&first),
+            vec![vec!["b.jpg", "a.jpg"], vec!["d.jpg"]]
+        );
+        let pair = &first.collected_photos.as_ref().unwrap()[0];
+        let last = pair.photos().last().unwrap();
+        assert_eq!(last.get_html_escaped_title(), "Pair");
+        assert_eq!(last.get_html_escaped_caption(), "Both");
+        assert_eq!(
+            pair.photos().next().unwrap().get_html_escaped_title(),
+            "b.jpg"
+        );
+
+        // The new photo is appended to the manifest, marked as new
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifest.containers.len(), 4);
+        let added = &manifest.containers[3];
+        assert_eq!(added.photos, ["d.jpg"]);
+        assert!(added.new);
+        assert!(manifest.containers[..3].iter().all(|entry| !entry.new));
+
+        // Once it is listed, the photo is placed like all others and not appended again
+        let mut second = named_album(&["a.jpg", "b.jpg", "c.jpg", "d.jpg"]);
+        second.apply_manifest(&path).unwrap();
+        assert_eq!(
+            containers(&second),
+            vec![vec!["b.jpg", "a.jpg"], vec!["d.jpg"]]
+        );
+        assert_eq!(Manifest::load(&path).unwrap().containers.len(), 4);
+    }
+
+    #[test]
+    fn albums_are_titled_with_their_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("radalbum-Album-Title-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let album =
+            Album::import_all_photos(&directory, &mut BuildCache::default(), false).unwrap();
+        assert_eq!(
+            album.title,
+            format!("radalbum-Album-Title-{}", std::process::id())
+        );
+        let album = album.with_title("Summer".to_string());
+        assert_eq!(album.title, "Summer");
+    }
+
 }
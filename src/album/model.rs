@@ -0,0 +1,109 @@
+use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::Album;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Version of the album model. It is increased with every change that breaks existing readers of
+/// the JSON export, while new optional fields keep the version.
+pub const MODEL_VERSION: u32 = 1;
+
+/// The album as it has been detected from the photo folder: the photos grouped into containers,
+/// in the order of the album.
+#[derive(Serialize, Deserialize)]
+pub struct AlbumModel {
+    pub version: u32,
+    /// Name and version of the program that wrote the model
+    pub generator: String,
+    pub title: String,
+    pub containers: Vec<ContainerModel>,
+}
+
+/// The kinds of photo containers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerKind {
+    SinglePhoto,
+    TwoPhotos,
+    ThreePhotos,
+    FourPhotos,
+    /// The start of a day, with the route and the statistics of the day
+    DayHeader,
+}
+
+/// A photo container and its photos, in the order they are shown.
+#[derive(Serialize, Deserialize)]
+pub struct ContainerModel {
+    #[serde(rename = "type")]
+    pub kind: ContainerKind,
+    pub photos: Vec<PhotoModel>,
+    /// The fields of the day, only set for day headers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<DayModel>,
+}
+
+impl ContainerModel {
+    /// A container of the given kind with the given photos.
+    pub fn new<'a>(kind: ContainerKind, photos: impl Iterator<Item = &'a Photo>) -> Self {
+        Self {
+            kind,
+            photos: photos.map(Photo::model).collect(),
+            day: None,
+        }
+    }
+}
+
+/// A photo with its metadata.
+#[derive(Serialize, Deserialize)]
+pub struct PhotoModel {
+    pub title: String,
+    /// The caption as written in the photo, in Markdown
+    pub caption: String,
+    /// Path of the source photo
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+}
+
+/// The fields of a day header, as parsed from the caption of its photo.
+/// The distance is given in kilometers, the ascent and the descent in meters and the average
+/// speed in kilometers per hour, all without their unit.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DayModel {
+    pub title: String,
+    /// The anchor of the day header inside the album
+    pub anchor: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub distance: Option<String>,
+    pub ascent: Option<String>,
+    pub descent: Option<String>,
+    pub moving_time: Option<String>,
+    pub avg_speed: Option<String>,
+    /// Path of the GPX track of the day
+    pub gpx: Option<PathBuf>,
+}
+
+impl AlbumModel {
+    /// The model of the given containers.
+    pub fn new(containers: &[Box<dyn PhotoContainer>], title: &str) -> Self {
+        Self {
+            version: MODEL_VERSION,
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            title: title.to_string(),
+            containers: containers
+                .iter()
+                .map(|container| container.model())
+                .collect(),
+        }
+    }
+
+    /// Write the model as pretty-printed JSON to the given path.
+    /// The file is only rewritten if its content changed.
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        json.push(b'\n');
+        Album::write_if_changed(path, &json)
+    }
+}
@@ -0,0 +1,212 @@
+use crate::album::pdf::document::{Color, PdfDocument, PT_PER_MM};
+use crate::album::pdf::layout::BOLD;
+use crate::album::pdf::{Margins, PageSettings, PdfSettings};
+use crate::album::photo::{image_processing, Photo};
+use crate::album::Album;
+use clap::ValueEnum;
+use std::io;
+use std::path::Path;
+
+/// Font size of the title on the front cover in points.
+const COVER_TITLE_SIZE: f32 = 28.0;
+
+/// Largest font size of the title on the spine in points.
+const SPINE_TITLE_SIZE: f32 = 10.0;
+
+/// Narrowest spine in millimeters that still gets the title printed onto it.
+const MIN_SPINE_FOR_TITLE: f32 = 6.0;
+
+/// Space between the title and the photo on the front cover in millimeters.
+const COVER_GAP: f32 = 8.0;
+
+const COVER_TITLE_COLOR: Color = [0.0, 0.0, 0.0];
+
+/// Print profiles of common photo books, as offered by online photo book printers.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PrintProfile {
+    /// A4 landscape photo book, 297 x 210 mm
+    PhotobookA4Landscape,
+    /// A4 portrait photo book, 210 x 297 mm
+    PhotobookA4Portrait,
+    /// Square photo book, 210 x 210 mm
+    PhotobookSquare,
+    /// Large square photo book, 300 x 300 mm
+    PhotobookLargeSquare,
+}
+
+impl PrintProfile {
+    /// The print file requirements of this photo book.
+    pub fn format(self) -> BookFormat {
+        let (width, height) = match self {
+            PrintProfile::PhotobookA4Landscape => (297.0, 210.0),
+            PrintProfile::PhotobookA4Portrait => (210.0, 297.0),
+            PrintProfile::PhotobookSquare => (210.0, 210.0),
+            PrintProfile::PhotobookLargeSquare => (300.0, 300.0),
+        };
+        BookFormat {
+            width,
+            height,
+            bleed: 3.0,
+            safe_zone: 10.0,
+            page_thickness: 0.08,
+            cover_allowance: 2.0,
+            min_pages: 24,
+            page_multiple: 2,
+            min_dpi: 200,
+        }
+    }
+}
+
+/// The requirements of a photo book printer for the interior and the cover file of a book.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookFormat {
+    /// Width of a trimmed page in millimeters
+    pub width: f32,
+    /// Height of a trimmed page in millimeters
+    pub height: f32,
+    /// Bleed around every page and the cover in millimeters
+    pub bleed: f32,
+    /// Distance in millimeters from the trim that must stay free of text and photos
+    pub safe_zone: f32,
+    /// Thickness in millimeters the spine grows per page
+    pub page_thickness: f32,
+    /// Thickness in millimeters the cover boards add to the spine
+    pub cover_allowance: f32,
+    /// Smallest number of pages of the book
+    pub min_pages: usize,
+    /// The number of pages must be a multiple of this number
+    pub page_multiple: usize,
+    /// Lowest resolution of the photos on the printed page, in dots per inch
+    pub min_dpi: u32,
+}
+
+impl BookFormat {
+    /// The pages of the interior, with margins of at least the safe zone.
+    pub fn page_settings(&self, margins: Margins) -> PageSettings {
+        let safe = |margin: f32| margin.max(self.safe_zone);
+        PageSettings {
+            width: self.width,
+            height: self.height,
+            margins: Margins {
+                top: safe(margins.top),
+                right: safe(margins.right),
+                bottom: safe(margins.bottom),
+                left: safe(margins.left),
+            },
+            bleed: self.bleed,
+            crop_marks: false,
+            flatten_transparency: true,
+        }
+    }
+
+    /// The number of pages of the printed book with the given number of laid out pages,
+    /// including the empty pages that are added at its end.
+    pub fn page_count(&self, pages: usize) -> usize {
+        pages
+            .max(self.min_pages)
+            .next_multiple_of(self.page_multiple)
+    }
+
+    /// The width of the spine in millimeters of a book with the given number of pages.
+    pub fn spine_width(&self, pages: usize) -> f32 {
+        self.page_count(pages) as f32 * self.page_thickness + self.cover_allowance
+    }
+
+    /// The cover spread of a book with the given number of pages, which is made of the back
+    /// cover, the spine and the front cover.
+    fn cover_settings(&self, pages: usize, crop_marks: bool) -> PageSettings {
+        PageSettings {
+            width: 2.0 * self.width + self.spine_width(pages),
+            height: self.height,
+            margins: Margins {
+                top: self.safe_zone,
+                right: self.safe_zone,
+                bottom: self.safe_zone,
+                left: self.safe_zone,
+            },
+            bleed: self.bleed,
+            crop_marks,
+            flatten_transparency: true,
+        }
+    }
+
+    /// Warn if the given photo is printed with a lower resolution than required, when it is
+    /// printed with the given width in millimeters.
+    pub fn check_resolution(&self, photo: &Photo, pixels: u32, width: f32) {
+        let dpi = pixels as f32 / (width / 25.4);
+        if dpi < self.min_dpi as f32 {
+            eprintln!(
+                "Warning: {} is printed with only {:.0} DPI, but the print profile requires {} DPI",
+                photo.get_path().display(),
+                dpi,
+                self.min_dpi
+            );
+        }
+    }
+}
+
+/// Write the cover spread of a book with the given number of pages to the given path.
+/// The front cover shows the title and the given photo, the spine shows the title if it is wide
+/// enough, and the back cover is left empty.
+pub fn write_cover(
+    title: &str,
+    photo: Option<&Photo>,
+    settings: &PdfSettings,
+    book: &BookFormat,
+    pages: usize,
+    path: &Path,
+) -> io::Result<()> {
+    let cover = book.cover_settings(pages, settings.page.crop_marks);
+    let mut document = PdfDocument::new(&cover, &[settings.regular.clone(), settings.bold.clone()]);
+    document.new_page();
+    let spine = book.spine_width(pages);
+
+    // Front cover
+    let front = book.width + spine;
+    let safe = book.safe_zone;
+    let title_width = settings.bold.text_width(title, COVER_TITLE_SIZE);
+    let baseline = safe + settings.bold.ascent(COVER_TITLE_SIZE);
+    document.draw_text(
+        BOLD,
+        COVER_TITLE_SIZE,
+        front + (book.width - title_width) / 2.0,
+        baseline,
+        title,
+        COVER_TITLE_COLOR,
+    );
+    if let Some(photo) = photo {
+        let (w, h) = match photo.get_size() {
+            Some(size) => size,
+            None => image_processing::displayed_size(photo.get_path())?,
+        };
+        let top = baseline + COVER_GAP;
+        let (area_width, area_height) = (book.width - 2.0 * safe, book.height - top - safe);
+        let scale = (area_width / w.max(1) as f32).min(area_height / h.max(1) as f32);
+        let (width, height) = (w as f32 * scale, h as f32 * scale);
+        book.check_resolution(photo, w, width);
+        document.draw_image(
+            photo.get_path(),
+            front + (book.width - width) / 2.0,
+            top,
+            width,
+            height,
+        )?;
+    }
+
+    // Spine
+    if spine >= MIN_SPINE_FOR_TITLE {
+        let size = SPINE_TITLE_SIZE.min(spine * 0.6 * PT_PER_MM);
+        let length = settings.bold.text_width(title, size);
+        // The baseline runs from top to bottom, with the glyphs centered on the spine
+        let x = book.width + (spine - settings.bold.ascent(size)) / 2.0;
+        document.draw_vertical_text(
+            BOLD,
+            size,
+            x,
+            (book.height - length) / 2.0,
+            title,
+            COVER_TITLE_COLOR,
+        );
+    }
+    Album::write_if_changed(path, &document.finish())
+}
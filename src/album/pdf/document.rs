@@ -0,0 +1,483 @@
+use crate::album::pdf::{PageSettings, PdfFont};
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::types::{CidFontType, FontFlags, SystemInfo, UnicodeCmap};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+
+/// PDF points per millimeter.
+pub const PT_PER_MM: f32 = 72.0 / 25.4;
+
+/// Compression level of all compressed streams, from 0 (none) to 10 (best).
+const COMPRESSION_LEVEL: u8 = 6;
+
+const SYSTEM_INFO: SystemInfo = SystemInfo {
+    registry: Str(b"Adobe"),
+    ordering: Str(b"Identity"),
+    supplement: 0,
+};
+
+/// Width in millimeters of the area around the bleed that holds the crop marks.
+const SLUG: f32 = 10.0;
+
+/// Distance in millimeters of the crop marks from the bleed.
+const CROP_MARK_OFFSET: f32 = 2.0;
+
+/// Line width of the crop marks in points.
+const CROP_MARK_WIDTH: f32 = 0.25;
+
+/// An RGB color with components from 0 to 1.
+pub type Color = [f32; 3];
+
+/// A font embedded into a [PdfDocument], together with all glyphs used with it.
+struct EmbeddedFont {
+    font: Arc<PdfFont>,
+    id: Ref,
+    /// Resource name of the font on the pages
+    resource: String,
+    /// All used glyphs by their glyph ID, together with the character they represent
+    glyphs: BTreeMap<u16, char>,
+}
+
+/// An image that has been embedded into a [PdfDocument].
+#[derive(Clone)]
+struct EmbeddedImage {
+    id: Ref,
+    /// Resource name of the image on the pages
+    resource: String,
+}
+
+/// A page that is currently drawn on.
+struct OpenPage {
+    content: Content,
+    /// Resource names and IDs of all images drawn on this page
+    images: BTreeMap<String, Ref>,
+}
+
+/// A PDF document that is written page by page.
+/// All positions and sizes are in millimeters, measured from the top left corner of the trimmed
+/// page. Fonts are embedded completely, photos are embedded as they are if they are JPEG files
+/// without rotation, and losslessly compressed otherwise.
+/// All colors and photos are written in RGB. If transparency is flattened, transparent images
+/// are flattened onto white.
+pub struct PdfDocument {
+    pdf: Pdf,
+    settings: PageSettings,
+    next_id: Ref,
+    page_tree: Ref,
+    pages: Vec<Ref>,
+    page: Option<OpenPage>,
+    fonts: Vec<EmbeddedFont>,
+    images: HashMap<PathBuf, EmbeddedImage>,
+}
+
+impl PdfDocument {
+    /// Create an empty document with the given page settings, which uses the given fonts.
+    /// The fonts are referenced by their index in this list when text is drawn.
+    pub fn new(settings: &PageSettings, fonts: &[Arc<PdfFont>]) -> Self {
+        let mut next_id = Ref::new(1);
+        let page_tree = next_id.bump();
+        let fonts = fonts
+            .iter()
+            .enumerate()
+            .map(|(i, font)| EmbeddedFont {
+                font: font.clone(),
+                id: next_id.bump(),
+                resource: format!("F{}", i),
+                glyphs: BTreeMap::new(),
+            })
+            .collect();
+        Self {
+            pdf: Pdf::new(),
+            settings: settings.clone(),
+            next_id,
+            page_tree,
+            pages: vec![],
+            page: None,
+            fonts,
+            images: HashMap::new(),
+        }
+    }
+
+    /// The number of pages started so far.
+    pub fn page_count(&self) -> usize {
+        self.pages.len() + usize::from(self.page.is_some())
+    }
+
+    /// Distance in millimeters between the border of the media box and the trimmed page.
+    fn offset(&self) -> f32 {
+        if self.settings.crop_marks {
+            self.settings.bleed + SLUG
+        } else {
+            self.settings.bleed
+        }
+    }
+
+    /// Convert a horizontal position on the trimmed page into PDF points.
+    fn x(&self, x: f32) -> f32 {
+        (self.offset() + x) * PT_PER_MM
+    }
+
+    /// Convert a vertical position from the top of the trimmed page into PDF points from the
+    /// bottom of the media box.
+    fn y(&self, y: f32) -> f32 {
+        (self.offset() + self.settings.height - y) * PT_PER_MM
+    }
+
+    /// Finish the current page and start a new, empty page.
+    pub fn new_page(&mut self) {
+        self.finish_page();
+        self.page = Some(OpenPage {
+            content: Content::new(),
+            images: BTreeMap::new(),
+        });
+    }
+
+    fn current_page(&mut self) -> &mut OpenPage {
+        if self.page.is_none() {
+            self.new_page();
+        }
+        self.page.as_mut().unwrap()
+    }
+
+    fn finish_page(&mut self) {
+        let Some(mut page) = self.page.take() else {
+            return;
+        };
+        if self.settings.crop_marks {
+            self.draw_crop_marks(&mut page.content);
+        }
+        let (width, height, bleed, offset) = (
+            self.settings.width * PT_PER_MM,
+            self.settings.height * PT_PER_MM,
+            self.settings.bleed * PT_PER_MM,
+            self.offset() * PT_PER_MM,
+        );
+        let page_id = self.next_id.bump();
+        let content_id = self.next_id.bump();
+        let mut pdf_page = self.pdf.page(page_id);
+        pdf_page
+            .parent(self.page_tree)
+            .media_box(Rect::new(
+                0.0,
+                0.0,
+                width + 2.0 * offset,
+                height + 2.0 * offset,
+            ))
+            .bleed_box(Rect::new(
+                offset - bleed,
+                offset - bleed,
+                width + offset + bleed,
+                height + offset + bleed,
+            ))
+            .trim_box(Rect::new(offset, offset, width + offset, height + offset))
+            .contents(content_id);
+        let mut resources = pdf_page.resources();
+        let mut fonts = resources.fonts();
+        for font in &self.fonts {
+            fonts.pair(Name(font.resource.as_bytes()), font.id);
+        }
+        fonts.finish();
+        let mut x_objects = resources.x_objects();
+        for (name, id) in &page.images {
+            x_objects.pair(Name(name.as_bytes()), *id);
+        }
+        x_objects.finish();
+        resources.finish();
+        pdf_page.finish();
+        let content = compress_to_vec_zlib(&page.content.finish(), COMPRESSION_LEVEL);
+        self.pdf
+            .stream(content_id, &content)
+            .filter(Filter::FlateDecode);
+        self.pages.push(page_id);
+    }
+
+    /// Draw crop marks at the corners of the trimmed page, outside of the bleed.
+    /// The marks are drawn in registration color, so that they appear on every printing plate.
+    fn draw_crop_marks(&self, content: &mut Content) {
+        let (width, height) = (self.settings.width, self.settings.height);
+        let start = self.settings.bleed + CROP_MARK_OFFSET;
+        let end = self.settings.bleed + SLUG;
+        content
+            .save_state()
+            .set_line_width(CROP_MARK_WIDTH)
+            .set_stroke_cmyk(1.0, 1.0, 1.0, 1.0);
+        for x in [0.0, width] {
+            for y in [0.0, height] {
+                // Direction pointing away from the page
+                let (dx, dy) = (
+                    if x == 0.0 { -1.0 } else { 1.0 },
+                    if y == 0.0 { -1.0 } else { 1.0 },
+                );
+                content
+                    .move_to(self.x(x + dx * start), self.y(y))
+                    .line_to(self.x(x + dx * end), self.y(y))
+                    .move_to(self.x(x), self.y(y + dy * start))
+                    .line_to(self.x(x), self.y(y + dy * end));
+            }
+        }
+        content.stroke().restore_state();
+    }
+
+    /// Fill the given rectangle with the given color.
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        let (px, py) = (self.x(x), self.y(y + height));
+        let [r, g, b] = color;
+        let content = &mut self.current_page().content;
+        content.save_state();
+        content.set_fill_rgb(r, g, b);
+        content
+            .rect(px, py, width * PT_PER_MM, height * PT_PER_MM)
+            .fill_nonzero()
+            .restore_state();
+    }
+
+    /// Draw a single line of text with its baseline at the given position.
+    /// Characters that are missing in the font are left out.
+    pub fn draw_text(&mut self, font: usize, size: f32, x: f32, y: f32, text: &str, color: Color) {
+        let (px, py) = (self.x(x), self.y(y));
+        self.show_text(font, size, [1.0, 0.0, 0.0, 1.0, px, py], text, color);
+    }
+
+    /// Draw a single line of text that runs from top to bottom, like on the spine of a book.
+    /// The given position is the start of the baseline.
+    pub fn draw_vertical_text(
+        &mut self,
+        font: usize,
+        size: f32,
+        x: f32,
+        y: f32,
+        text: &str,
+        color: Color,
+    ) {
+        let (px, py) = (self.x(x), self.y(y));
+        self.show_text(font, size, [0.0, -1.0, 1.0, 0.0, px, py], text, color);
+    }
+
+    /// Draw a single line of text with the given text matrix in PDF points.
+    fn show_text(&mut self, font: usize, size: f32, matrix: [f32; 6], text: &str, color: Color) {
+        let embedded = &mut self.fonts[font];
+        let mut encoded = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            if let Some(glyph) = embedded.font.glyph(c) {
+                embedded.glyphs.entry(glyph).or_insert(c);
+                encoded.extend(glyph.to_be_bytes());
+            }
+        }
+        let resource = embedded.resource.clone();
+        let [r, g, b] = color;
+        let content = &mut self.current_page().content;
+        content.save_state();
+        content.set_fill_rgb(r, g, b);
+        content
+            .begin_text()
+            .set_font(Name(resource.as_bytes()), size)
+            .set_text_matrix(matrix)
+            .show(Str(&encoded))
+            .end_text()
+            .restore_state();
+    }
+
+    /// Draw the image at the given path into the given rectangle, stretching it if the aspect
+    /// ratio differs. Images used several times are only embedded once.
+    pub fn draw_image(
+        &mut self,
+        path: &Path,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> io::Result<()> {
+        let image = match self.images.get(path) {
+            Some(image) => image.clone(),
+            None => {
+                let image = self.embed_image(path)?;
+                self.images.insert(path.to_path_buf(), image.clone());
+                image
+            },
+        };
+        let (px, py) = (self.x(x), self.y(y + height));
+        let page = self.current_page();
+        page.images.insert(image.resource.clone(), image.id);
+        page.content
+            .save_state()
+            .transform([width * PT_PER_MM, 0.0, 0.0, height * PT_PER_MM, px, py])
+            .x_object(Name(image.resource.as_bytes()))
+            .restore_state();
+        Ok(())
+    }
+
+    /// Read the size and the number of color components of a baseline or progressive JPEG
+    /// image from its frame header.
+    fn jpeg_frame(data: &[u8]) -> Option<(u32, u32, u8)> {
+        let mut i = 2;
+        while i + 9 < data.len() {
+            if data[i] != 0xFF {
+                return None;
+            }
+            let marker = data[i + 1];
+            let length = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+            if (0xC0..=0xC2).contains(&marker) {
+                let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+                let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+                return Some((width, height, data[i + 9]));
+            }
+            i += 2 + length;
+        }
+        None
+    }
+
+    /// Embed the image at the given path as image XObject.
+    fn embed_image(&mut self, path: &Path) -> io::Result<EmbeddedImage> {
+        let id = self.next_id.bump();
+        let resource = format!("Im{}", self.images.len());
+        let reader = ImageReader::open(path)?.with_guessed_format()?;
+        let format = reader.format();
+        let mut decoder = reader.into_decoder().map_err(io::Error::other)?;
+        let orientation = decoder.orientation().map_err(io::Error::other)?;
+        if format == Some(ImageFormat::Jpeg) && orientation == Orientation::NoTransforms {
+            let data = fs::read(path)?;
+            if let Some((width, height, components @ (1 | 3))) = Self::jpeg_frame(&data) {
+                let mut image = self.pdf.image_xobject(id, &data);
+                image.filter(Filter::DctDecode);
+                image
+                    .width(width as i32)
+                    .height(height as i32)
+                    .bits_per_component(8);
+                if components == 1 {
+                    image.color_space().device_gray();
+                } else {
+                    image.color_space().device_rgb();
+                }
+                return Ok(EmbeddedImage { id, resource });
+            }
+        }
+        // Everything else is decoded and stored losslessly
+        let mut decoded = DynamicImage::from_decoder(decoder).map_err(io::Error::other)?;
+        decoded.apply_orientation(orientation);
+        let (width, height) = (decoded.width() as i32, decoded.height() as i32);
+        if self.settings.flatten_transparency && decoded.color().has_alpha() {
+            // Flatten transparency onto white, as transparency is not allowed in print files
+            let mut flattened = decoded.to_rgba8();
+            for pixel in flattened.pixels_mut() {
+                let alpha = pixel[3] as f32 / 255.0;
+                for channel in 0..3 {
+                    let value = pixel[channel] as f32 * alpha + 255.0 * (1.0 - alpha);
+                    pixel[channel] = value.round() as u8;
+                }
+                pixel[3] = 255;
+            }
+            decoded = DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(flattened).to_rgb8());
+        }
+        let mask = if decoded.color().has_alpha() {
+            let alpha = decoded
+                .to_rgba8()
+                .pixels()
+                .map(|pixel| pixel[3])
+                .collect::<Vec<_>>();
+            let mask_id = self.next_id.bump();
+            let alpha = compress_to_vec_zlib(&alpha, COMPRESSION_LEVEL);
+            let mut mask = self.pdf.image_xobject(mask_id, &alpha);
+            mask.filter(Filter::FlateDecode);
+            mask.width(width).height(height).bits_per_component(8);
+            mask.color_space().device_gray();
+            mask.finish();
+            Some(mask_id)
+        } else {
+            None
+        };
+        let rgb = compress_to_vec_zlib(decoded.to_rgb8().as_raw(), COMPRESSION_LEVEL);
+        let mut image = self.pdf.image_xobject(id, &rgb);
+        image.filter(Filter::FlateDecode);
+        image.width(width).height(height).bits_per_component(8);
+        image.color_space().device_rgb();
+        if let Some(mask_id) = mask {
+            image.s_mask(mask_id);
+        }
+        Ok(EmbeddedImage { id, resource })
+    }
+
+    /// Embed a font as composite font, with the widths and the Unicode mapping of all used
+    /// glyphs.
+    fn embed_font(pdf: &mut Pdf, next_id: &mut Ref, font: &EmbeddedFont) {
+        let (cid_id, descriptor_id, file_id, cmap_id) = (
+            next_id.bump(),
+            next_id.bump(),
+            next_id.bump(),
+            next_id.bump(),
+        );
+        let name = font.font.name.as_bytes();
+        let scale = 1000.0 / font.font.units_per_em();
+        let (ascent, descent) = (
+            font.font.ascent_unscaled() * scale,
+            font.font.descent_unscaled() * scale,
+        );
+        let bbox = match font.font.bounding_box_unscaled() {
+            Some([left, bottom, right, top]) => {
+                Rect::new(left * scale, bottom * scale, right * scale, top * scale)
+            },
+            None => Rect::new(0.0, descent, 1000.0, ascent),
+        };
+
+        pdf.type0_font(font.id)
+            .base_font(Name(name))
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid_id)
+            .to_unicode(cmap_id);
+
+        let mut cid = pdf.cid_font(cid_id);
+        cid.subtype(CidFontType::Type2)
+            .base_font(Name(name))
+            .system_info(SYSTEM_INFO)
+            .font_descriptor(descriptor_id)
+            .default_width(0.0)
+            .cid_to_gid_map_predefined(Name(b"Identity"));
+        let mut widths = cid.widths();
+        for &glyph in font.glyphs.keys() {
+            widths.consecutive(glyph, [font.font.advance_unscaled(glyph) * scale]);
+        }
+        widths.finish();
+        cid.finish();
+
+        pdf.font_descriptor(descriptor_id)
+            .name(Name(name))
+            .flags(FontFlags::NON_SYMBOLIC)
+            .bbox(bbox)
+            .italic_angle(0.0)
+            .ascent(ascent)
+            .descent(descent)
+            .cap_height(ascent)
+            .stem_v(80.0)
+            .font_file2(file_id);
+
+        let data = compress_to_vec_zlib(&font.font.data, COMPRESSION_LEVEL);
+        pdf.stream(file_id, &data)
+            .filter(Filter::FlateDecode)
+            .pair(Name(b"Length1"), font.font.data.len() as i32);
+
+        let mut cmap = UnicodeCmap::new(Name(b"Custom"), SYSTEM_INFO);
+        for (&glyph, &c) in &font.glyphs {
+            cmap.pair(glyph, c);
+        }
+        pdf.cmap(cmap_id, &cmap.finish());
+    }
+
+    /// Finish the last page, embed all fonts and return the complete PDF file.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.finish_page();
+        for font in &self.fonts {
+            Self::embed_font(&mut self.pdf, &mut self.next_id, font);
+        }
+        let catalog = self.next_id.bump();
+        self.pdf
+            .pages(self.page_tree)
+            .kids(self.pages.iter().copied())
+            .count(self.pages.len() as i32);
+        self.pdf.catalog(catalog).pages(self.page_tree);
+        self.pdf.finish()
+    }
+}
@@ -0,0 +1,540 @@
+use crate::album::pdf::document::{Color, PdfDocument, PT_PER_MM};
+use crate::album::pdf::{PageBreak, PdfFont, PdfSettings, PrintBlock, PrintLayout};
+use crate::album::photo::image_processing;
+use crate::album::photo::Photo;
+use std::io;
+
+/// Index of the regular font in the document.
+const REGULAR: usize = 0;
+/// Index of the bold font in the document.
+pub(crate) const BOLD: usize = 1;
+
+/// Font sizes in points
+const HEADING_SIZE: f32 = 22.0;
+const DETAILS_SIZE: f32 = 10.0;
+const TITLE_SIZE: f32 = 14.0;
+const TEXT_SIZE: f32 = 10.0;
+const PAGE_NUMBER_SIZE: f32 = 8.0;
+
+/// Line height as multiple of the font size.
+const LINE_HEIGHT: f32 = 1.35;
+
+/// Space between two photos and below every block in millimeters.
+const GAP: f32 = 4.0;
+
+/// Space between the border of a text block and its text in millimeters.
+const TEXT_PADDING: f32 = 3.0;
+
+/// Maximum height of a row of photos, as fraction of the content height.
+const MAX_ROW_HEIGHT: f32 = 0.75;
+
+/// Colors of the album, matching `Album.css`
+const HEADING_COLOR: Color = [
+    0x36 as f32 / 255.0,
+    0x1E as f32 / 255.0,
+    0x20 as f32 / 255.0,
+];
+const TEXT_COLOR: Color = [
+    0x17 as f32 / 255.0,
+    0x1D as f32 / 255.0,
+    0x1A as f32 / 255.0,
+];
+const TEXT_BACKGROUND: Color = [
+    0xF5 as f32 / 255.0,
+    0xF0 as f32 / 255.0,
+    0xE9 as f32 / 255.0,
+];
+
+/// Lays out the photo containers of the album one below the other onto pages.
+/// Blocks that must stay together and do not fit onto the rest of the current page are moved to
+/// the next page. Text blocks that are higher than a page are split between lines.
+pub struct PdfLayout<'a> {
+    settings: &'a PdfSettings,
+    document: PdfDocument,
+    /// Vertical position of the top of the next block, from the top of the page, in millimeters
+    y: f32,
+    /// Whether the photos are left out, because only the positions of the blocks are needed
+    draft: bool,
+    /// The page number every chapter starts on
+    chapter_pages: Vec<usize>,
+}
+
+impl<'a> PdfLayout<'a> {
+    pub fn new(settings: &'a PdfSettings) -> Self {
+        Self {
+            settings,
+            document: PdfDocument::new(
+                &settings.page,
+                &[settings.regular.clone(), settings.bold.clone()],
+            ),
+            y: 0.0,
+            draft: false,
+            chapter_pages: vec![],
+        }
+    }
+
+    /// A layout that places all blocks exactly like [PdfLayout::new], but leaves out the photos.
+    /// It is used to find the page numbers of the chapters before the album is laid out.
+    pub fn draft(settings: &'a PdfSettings) -> Self {
+        Self {
+            draft: true,
+            ..Self::new(settings)
+        }
+    }
+
+    fn font(&self, font: usize) -> &PdfFont {
+        if font == BOLD {
+            &self.settings.bold
+        } else {
+            &self.settings.regular
+        }
+    }
+
+    /// The vertical position of the bottom margin.
+    fn bottom(&self) -> f32 {
+        self.settings.page.height - self.settings.page.margins.bottom
+    }
+
+    /// The space left on the current page in millimeters.
+    fn remaining(&self) -> f32 {
+        self.bottom() - self.y
+    }
+
+    /// Start a new page, with its page number centered in the bottom margin.
+    fn new_page(&mut self) {
+        self.document.new_page();
+        self.y = self.settings.page.margins.top;
+        let number = self.document.page_count().to_string();
+        let page = &self.settings.page;
+        let width = self.settings.regular.text_width(&number, PAGE_NUMBER_SIZE);
+        let baseline = page.height - page.margins.bottom / 2.0;
+        self.document.draw_text(
+            REGULAR,
+            PAGE_NUMBER_SIZE,
+            (page.width - width) / 2.0,
+            baseline,
+            &number,
+            TEXT_COLOR,
+        );
+    }
+
+    /// Make sure that a block of the given height fits onto the current page, by starting a new
+    /// page if necessary. Blocks that are higher than a whole page are placed on a new page.
+    fn reserve(&mut self, height: f32) {
+        if self.document.page_count() == 0 || (height > self.remaining() && self.page_started()) {
+            self.new_page();
+        }
+    }
+
+    /// Whether something has been placed onto the current page.
+    fn page_started(&self) -> bool {
+        self.y > self.settings.page.margins.top
+    }
+
+    /// Start a new page for a new chapter, according to the given page break.
+    fn break_page(&mut self, page_break: PageBreak) {
+        if page_break == PageBreak::None {
+            return;
+        }
+        if self.document.page_count() == 0 || self.page_started() {
+            self.new_page();
+        }
+        // The first page is a right-hand page
+        if page_break == PageBreak::RightPage && self.document.page_count().is_multiple_of(2) {
+            self.new_page();
+        }
+    }
+
+    fn line_height(size: f32) -> f32 {
+        size * LINE_HEIGHT / PT_PER_MM
+    }
+
+    /// Break the given text into lines that are at most `width` millimeters wide.
+    /// Line breaks inside the text are kept. Words that are too long stay on their own line.
+    fn wrap(font: &PdfFont, size: f32, text: &str, width: f32) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in text.lines() {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if line.is_empty() {
+                    word.to_string()
+                } else {
+                    format!("{} {}", line, word)
+                };
+                if !line.is_empty() && font.text_width(&candidate, size) > width {
+                    lines.push(line);
+                    line = word.to_string();
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// The font, font size, color and background color of the given text block.
+    fn text_style(block: &PrintBlock) -> (usize, f32, Color, Option<Color>) {
+        match block {
+            PrintBlock::Heading(_) => (BOLD, HEADING_SIZE, HEADING_COLOR, None),
+            PrintBlock::Details(_) => (REGULAR, DETAILS_SIZE, TEXT_COLOR, None),
+            PrintBlock::Title(_) => (BOLD, TITLE_SIZE, TEXT_COLOR, None),
+            PrintBlock::Caption { .. } | PrintBlock::Row(_) => {
+                (REGULAR, TEXT_SIZE, TEXT_COLOR, Some(TEXT_BACKGROUND))
+            },
+        }
+    }
+
+    fn padding(background: Option<Color>) -> f32 {
+        if background.is_some() {
+            TEXT_PADDING
+        } else {
+            0.0
+        }
+    }
+
+    /// Break the given text block into lines across the content width.
+    fn text_lines(&self, block: &PrintBlock, text: &str) -> Vec<String> {
+        let (font, size, _, background) = Self::text_style(block);
+        let width = self.settings.page.content_width() - 2.0 * Self::padding(background);
+        Self::wrap(self.font(font), size, text, width)
+    }
+
+    /// Place the given text block across the content width. Text blocks with a background color
+    /// are drawn onto a box of this color.
+    fn place_text(&mut self, block: &PrintBlock, text: &str) {
+        let (font, size, color, background) = Self::text_style(block);
+        let padding = Self::padding(background);
+        let (left, width) = (
+            self.settings.page.margins.left,
+            self.settings.page.content_width(),
+        );
+        let lines = self.text_lines(block, text);
+        let line_height = Self::line_height(size);
+        let ascent = self.font(font).ascent(size);
+        // Center the glyphs vertically within their line
+        let baseline = (line_height - size / PT_PER_MM) / 2.0 + ascent;
+        let mut rest = &lines[..];
+        while !rest.is_empty() {
+            self.reserve(line_height + 2.0 * padding);
+            let fitting = ((self.remaining() - 2.0 * padding) / line_height).floor() as usize;
+            let (now, later) = rest.split_at(fitting.clamp(1, rest.len()));
+            let height = now.len() as f32 * line_height + 2.0 * padding;
+            if let Some(background) = background {
+                self.document
+                    .fill_rect(left, self.y, width, height, background);
+            }
+            for (i, line) in now.iter().enumerate() {
+                let y = self.y + padding + i as f32 * line_height + baseline;
+                self.document
+                    .draw_text(font, size, left + padding, y, line, color);
+            }
+            self.y += height;
+            rest = later;
+        }
+        self.y += GAP;
+    }
+
+    /// The displayed size of the given photo.
+    fn photo_size(photo: &Photo) -> io::Result<(u32, u32)> {
+        match photo.get_size() {
+            Some(size) => Ok(size),
+            None => image_processing::displayed_size(photo.get_path()),
+        }
+    }
+
+    /// The width of each slot, the height and the sizes of the photos of a row with the given
+    /// photos.
+    fn row_size(&self, photos: &[&Photo]) -> io::Result<(f32, f32, Vec<(u32, u32)>)> {
+        let page = &self.settings.page;
+        let count = photos.len() as f32;
+        let slot = (page.content_width() - GAP * (count - 1.0)) / count;
+        let sizes = photos
+            .iter()
+            .map(|photo| Self::photo_size(photo))
+            .collect::<io::Result<Vec<_>>>()?;
+        let height = sizes
+            .iter()
+            .map(|(w, h)| slot * *h as f32 / (*w).max(1) as f32)
+            .fold(0.0, f32::max)
+            .min(page.content_height() * MAX_ROW_HEIGHT);
+        Ok((slot, height, sizes))
+    }
+
+    /// Place the given photos next to each other, in slots of equal width.
+    /// The photos keep their aspect ratio and are aligned at the top of the row.
+    fn place_row(&mut self, photos: &[&Photo]) -> io::Result<()> {
+        if photos.is_empty() {
+            return Ok(());
+        }
+        let (slot, height, sizes) = self.row_size(photos)?;
+        let left = self.settings.page.margins.left;
+        self.reserve(height);
+        for (i, (photo, (w, h))) in photos.iter().zip(sizes).enumerate() {
+            let scale = (slot / w.max(1) as f32).min(height / h.max(1) as f32);
+            let (width, photo_height) = (w as f32 * scale, h as f32 * scale);
+            let x = left + i as f32 * (slot + GAP) + (slot - width) / 2.0;
+            if self.draft {
+                continue;
+            }
+            if let Some(book) = &self.settings.book {
+                book.check_resolution(photo, w, width);
+            }
+            self.document
+                .draw_image(photo.get_path(), x, self.y, width, photo_height)?;
+        }
+        self.y += height + GAP;
+        Ok(())
+    }
+
+    /// The height in millimeters the given block needs, without the gap below it.
+    fn height(&self, block: &PrintBlock) -> io::Result<f32> {
+        match block {
+            PrintBlock::Row(photos) if photos.is_empty() => Ok(0.0),
+            PrintBlock::Row(photos) => self.row_size(photos).map(|(_, height, _)| height),
+            PrintBlock::Heading(text)
+            | PrintBlock::Details(text)
+            | PrintBlock::Title(text)
+            | PrintBlock::Caption { text, .. } => {
+                let (_, size, _, background) = Self::text_style(block);
+                let lines = self.text_lines(block, text).len() as f32;
+                Ok(lines * Self::line_height(size) + 2.0 * Self::padding(background))
+            },
+        }
+    }
+
+    /// Place the given block below the previous one.
+    fn place_block(&mut self, block: &PrintBlock) -> io::Result<()> {
+        match block {
+            PrintBlock::Row(photos) => self.place_row(photos)?,
+            PrintBlock::Heading(text)
+            | PrintBlock::Details(text)
+            | PrintBlock::Title(text)
+            | PrintBlock::Caption { text, .. } => self.place_text(block, text),
+        }
+        Ok(())
+    }
+
+    /// Place the given photo container below the previous one.
+    /// A chapter starts on a new page according to the settings. All blocks that must stay
+    /// together are moved to the next page if they do not fit onto the current page.
+    pub fn place(&mut self, container: &PrintLayout) -> io::Result<()> {
+        if container.chapter {
+            self.break_page(self.settings.chapter_break);
+        }
+        for (i, group) in container.keep_together().into_iter().enumerate() {
+            let height = group
+                .iter()
+                .map(|block| self.height(block))
+                .sum::<io::Result<f32>>()?
+                + GAP * (group.len().max(1) - 1) as f32;
+            self.reserve(height);
+            if container.chapter && i == 0 {
+                self.chapter_pages.push(self.document.page_count());
+            }
+            for block in group {
+                self.place_block(block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Place a table of contents with the given entries and page numbers. The page numbers are
+    /// right-aligned, and the text of an entry is wrapped before them. The width of the page
+    /// numbers does not change the layout, so that a draft with other page numbers places the
+    /// entries at the same positions.
+    pub fn place_contents(&mut self, entries: &[(String, usize)]) {
+        let (size, color) = (TEXT_SIZE, TEXT_COLOR);
+        let (left, width) = (
+            self.settings.page.margins.left,
+            self.settings.page.content_width(),
+        );
+        let line_height = Self::line_height(size);
+        let baseline = (line_height - size / PT_PER_MM) / 2.0 + self.font(REGULAR).ascent(size);
+        let number_width = self.font(REGULAR).text_width("0000", size);
+        for (text, page) in entries {
+            let lines = Self::wrap(self.font(REGULAR), size, text, width - number_width - GAP);
+            self.reserve(lines.len() as f32 * line_height);
+            for line in &lines {
+                self.document
+                    .draw_text(REGULAR, size, left, self.y + baseline, line, color);
+                self.y += line_height;
+            }
+            // The page number is next to the last line of the entry
+            let number = page.to_string();
+            let x = left + width - self.font(REGULAR).text_width(&number, size);
+            self.document.draw_text(
+                REGULAR,
+                size,
+                x,
+                self.y - line_height + baseline,
+                &number,
+                color,
+            );
+        }
+        self.y += GAP;
+    }
+
+    /// The page number every chapter placed so far starts on, in the order of the chapters.
+    pub fn chapter_pages(&self) -> &[usize] {
+        &self.chapter_pages
+    }
+
+    /// The number of pages started so far.
+    pub fn page_count(&self) -> usize {
+        self.document.page_count()
+    }
+
+    /// Finish the layout and return the complete PDF file.
+    /// For a photo book, empty pages are added until the book has a valid number of pages.
+    pub fn finish(mut self) -> Vec<u8> {
+        let pages = match &self.settings.book {
+            Some(book) => book.page_count(self.document.page_count()),
+            None => self.document.page_count().max(1),
+        };
+        while self.document.page_count() < pages {
+            self.new_page();
+        }
+        self.document.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::album::assets::AssetFile;
+    use crate::album::pdf::{Margins, PageSettings, PaperSize};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// The bundled font with the given file name.
+    fn font(name: &str) -> Arc<PdfFont> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets/fonts")
+            .join(name);
+        Arc::new(PdfFont::new(AssetFile::load(&path).unwrap()).unwrap())
+    }
+
+    fn settings(chapter_break: PageBreak) -> PdfSettings {
+        let margins = Margins {
+            top: 10.0,
+            right: 10.0,
+            bottom: 15.0,
+            left: 10.0,
+        };
+        PdfSettings {
+            page: PageSettings::new(PaperSize(148.0, 210.0), false, margins, 0.0),
+            regular: font("DejaVuSans.ttf"),
+            bold: font("DejaVuSans-Bold.ttf"),
+            chapter_break,
+            book: None,
+        }
+    }
+
+    fn headings(count: usize, splittable: bool, chapter: bool) -> PrintLayout<'static> {
+        PrintLayout {
+            blocks: (0..count)
+                .map(|i| PrintBlock::Heading(format!("Day {}", i + 1)))
+                .collect(),
+            splittable,
+            chapter,
+        }
+    }
+
+    /// Start a page on which only the given height in millimeters is left.
+    fn fill_page(layout: &mut PdfLayout, remaining: f32) {
+        layout.reserve(0.0);
+        layout.y = layout.bottom() - remaining;
+    }
+
+    #[test]
+    fn chapters_start_on_right_pages() {
+        let settings = settings(PageBreak::RightPage);
+        let mut layout = PdfLayout::new(&settings);
+        layout.place(&headings(1, false, true)).unwrap();
+        assert_eq!(layout.document.page_count(), 1);
+        // The second chapter would start on the left page 2, so that page stays empty
+        layout.place(&headings(1, false, true)).unwrap();
+        assert_eq!(layout.document.page_count(), 3);
+        // Containers that are not chapters continue on the same page
+        layout.place(&headings(1, false, false)).unwrap();
+        assert_eq!(layout.document.page_count(), 3);
+
+        let settings = self::settings(PageBreak::Page);
+        let mut layout = PdfLayout::new(&settings);
+        layout.place(&headings(1, false, true)).unwrap();
+        layout.place(&headings(1, false, true)).unwrap();
+        assert_eq!(layout.document.page_count(), 2);
+    }
+
+    #[test]
+    fn blocks_that_do_not_fit_move_to_the_next_page() {
+        let settings = settings(PageBreak::None);
+        let mut layout = PdfLayout::new(&settings);
+        let height = layout
+            .height(&PrintBlock::Heading("Day 1".to_string()))
+            .unwrap();
+        fill_page(&mut layout, height + GAP);
+        layout.place(&headings(1, false, false)).unwrap();
+        assert_eq!(layout.document.page_count(), 1);
+        layout.place(&headings(1, false, false)).unwrap();
+        assert_eq!(layout.document.page_count(), 2);
+        assert_eq!(layout.y, settings.page.margins.top + height + GAP);
+    }
+
+    #[test]
+    fn blocks_kept_together_move_as_a_whole() {
+        let settings = settings(PageBreak::None);
+        let mut layout = PdfLayout::new(&settings);
+        let height = layout
+            .height(&PrintBlock::Heading("Day 1".to_string()))
+            .unwrap();
+        // Only the first of both headings would fit onto the first page
+        fill_page(&mut layout, height + GAP);
+        layout.place(&headings(2, false, false)).unwrap();
+        assert_eq!(layout.document.page_count(), 2);
+        let bottom = settings.page.margins.top + 2.0 * (height + GAP);
+        assert!((layout.y - bottom).abs() < 0.001);
+    }
+
+    #[test]
+    fn captions_stay_with_the_last_row() {
+        let photo = Photo::for_test("input/photo.jpg", "Title", "Caption");
+        let container = PrintLayout {
+            blocks: vec![
+                PrintBlock::Heading("Day 1".to_string()),
+                PrintBlock::Row(vec![]),
+                PrintBlock::Row(vec![]),
+                PrintBlock::Title("Title".to_string()),
+                PrintBlock::Caption {
+                    text: "Caption".to_string(),
+                    photo: &photo,
+                },
+            ],
+            splittable: true,
+            chapter: false,
+        };
+        let groups = container
+            .keep_together()
+            .iter()
+            .map(|group| group.len())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![2, 3]);
+
+        let settings = settings(PageBreak::None);
+        let mut layout = PdfLayout::new(&settings);
+        let height = layout
+            .height(&PrintBlock::Heading("Day 1".to_string()))
+            .unwrap();
+        // The heading and the first row stay on the first page, the rest moves on
+        fill_page(&mut layout, height + GAP);
+        layout.place(&container).unwrap();
+        assert_eq!(layout.document.page_count(), 2);
+
+        let unsplittable = PrintLayout {
+            splittable: false,
+            ..container
+        };
+        assert_eq!(unsplittable.keep_together().len(), 1);
+    }
+}
@@ -0,0 +1,418 @@
+pub(crate) mod book;
+pub(crate) mod document;
+pub(crate) mod layout;
+
+use crate::album::assets::AssetFile;
+use crate::album::pdf::book::BookFormat;
+use crate::album::pdf::layout::PdfLayout;
+use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::Album;
+use ab_glyph::{Font, FontVec};
+use clap::ValueEnum;
+use itertools::Itertools;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Title of the table of contents.
+const CONTENTS_TITLE: &str = "Contents";
+
+/// How the PDF album is built.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PdfBackend {
+    /// Lay out the pages and write the PDF file directly, without external programs
+    Native,
+    /// Convert the Markdown file with pandoc and wkhtmltopdf into a single long page
+    Pandoc,
+}
+
+/// Where a new chapter, e.g. a day, starts in the native PDF album.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PageBreak {
+    /// Directly below the previous photos, if it fits onto the page
+    None,
+    /// On a new page
+    Page,
+    /// On a new right-hand page, leaving the page before it empty if necessary
+    RightPage,
+}
+
+/// Width and height of the trimmed page in millimeters, in portrait orientation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PaperSize(pub f32, pub f32);
+
+impl FromStr for PaperSize {
+    type Err = String;
+
+    /// Parse a paper size, either a well-known name like `a4`, `letter` or the photo book size
+    /// `square`, or the width and the height in millimeters like `210x297`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = match s.trim().to_lowercase().as_str() {
+            "a3" => PaperSize(297.0, 420.0),
+            "a4" => PaperSize(210.0, 297.0),
+            "a5" => PaperSize(148.0, 210.0),
+            "a6" => PaperSize(105.0, 148.0),
+            // Common sizes of printed photo books
+            "square" => PaperSize(210.0, 210.0),
+            "square-large" => PaperSize(300.0, 300.0),
+            "letter" => PaperSize(215.9, 279.4),
+            "legal" => PaperSize(215.9, 355.6),
+            size => {
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or(format!("Unknown paper size: {}", s))?;
+                let parse = |v: &str| {
+                    v.trim()
+                        .parse::<f32>()
+                        .map_err(|e| format!("Invalid paper size {}: {}", s, e))
+                };
+                PaperSize(parse(width)?, parse(height)?)
+            },
+        };
+        Ok(size)
+    }
+}
+
+/// Margins of the printed content in millimeters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl FromStr for Margins {
+    type Err = String;
+
+    /// Parse margins in millimeters, either a single value for all sides, or four values in the
+    /// order top, right, bottom, left, like in CSS, e.g. `20,15,20,15`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid margin: {}", e))?;
+        match values[..] {
+            [all] => Ok(Self {
+                top: all,
+                right: all,
+                bottom: all,
+                left: all,
+            }),
+            [top, right, bottom, left] => Ok(Self {
+                top,
+                right,
+                bottom,
+                left,
+            }),
+            _ => Err("Margins must be given as one value or as TOP,RIGHT,BOTTOM,LEFT".to_string()),
+        }
+    }
+}
+
+/// The pages of the native PDF album.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageSettings {
+    /// Width of the trimmed page in millimeters
+    pub width: f32,
+    /// Height of the trimmed page in millimeters
+    pub height: f32,
+    pub margins: Margins,
+    /// Extra area in millimeters around the trimmed page, which is cut off after printing
+    pub bleed: f32,
+    /// Whether crop marks are drawn outside of the bleed, at the corners of the trimmed page
+    pub crop_marks: bool,
+    /// Whether transparent photos are flattened onto white, as transparency is not allowed in
+    /// most print files
+    pub flatten_transparency: bool,
+}
+
+impl PageSettings {
+    pub fn new(paper: PaperSize, landscape: bool, margins: Margins, bleed: f32) -> Self {
+        let PaperSize(short, long) = paper;
+        let (width, height) = if landscape {
+            (short.max(long), short.min(long))
+        } else {
+            (short, long)
+        };
+        Self {
+            width,
+            height,
+            margins,
+            bleed,
+            crop_marks: false,
+            flatten_transparency: false,
+        }
+    }
+
+    /// Draw crop marks around the pages.
+    pub fn with_crop_marks(mut self, crop_marks: bool) -> Self {
+        self.crop_marks = crop_marks;
+        self
+    }
+
+    /// Width of the area between the left and the right margin in millimeters.
+    pub fn content_width(&self) -> f32 {
+        self.width - self.margins.left - self.margins.right
+    }
+
+    /// Height of the area between the top and the bottom margin in millimeters.
+    pub fn content_height(&self) -> f32 {
+        self.height - self.margins.top - self.margins.bottom
+    }
+}
+
+/// A TrueType or OpenType font that is embedded into the PDF album.
+pub struct PdfFont {
+    /// Name of the font inside the PDF file
+    name: String,
+    /// The content of the font file
+    data: Vec<u8>,
+    font: FontVec,
+    /// Characters without a glyph that have already been warned about
+    missing: Mutex<BTreeSet<char>>,
+}
+
+impl PdfFont {
+    /// Load the given font file.
+    pub fn new(file: AssetFile) -> io::Result<Self> {
+        let data = file.content.into_owned();
+        let font = FontVec::try_from_vec(data.clone())
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{}: {}", file.name, e)))?;
+        let name = Path::new(&file.name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect::<String>();
+        Ok(Self {
+            name,
+            data,
+            font,
+            missing: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    /// The glyph ID of the given character, if the font contains it.
+    /// Missing characters are left out of the album, with a warning for each of them.
+    fn glyph(&self, c: char) -> Option<u16> {
+        let glyph = Some(self.font.glyph_id(c).0).filter(|id| *id != 0);
+        if glyph.is_none() && !c.is_control() {
+            let mut missing = self.missing.lock().unwrap_or_else(|e| e.into_inner());
+            if missing.insert(c) {
+                eprintln!(
+                    "The font {} has no glyph for {:?} (U+{:04X}), leaving it out of the PDF album!",
+                    self.name, c, c as u32
+                );
+            }
+        }
+        glyph
+    }
+
+    fn units_per_em(&self) -> f32 {
+        self.font.units_per_em().unwrap_or(1000.0)
+    }
+
+    fn ascent_unscaled(&self) -> f32 {
+        self.font.ascent_unscaled()
+    }
+
+    fn descent_unscaled(&self) -> f32 {
+        self.font.descent_unscaled()
+    }
+
+    /// The bounding box of all glyphs of the font in font units, as left, bottom, right and top
+    /// edge, read from the `head` table of the font file. Returns none if the table is missing.
+    fn bounding_box_unscaled(&self) -> Option<[f32; 4]> {
+        let read_u16 = |at: usize| {
+            Some(u16::from_be_bytes(
+                self.data.get(at..at + 2)?.try_into().ok()?,
+            ))
+        };
+        let read_u32 = |at: usize| {
+            Some(u32::from_be_bytes(
+                self.data.get(at..at + 4)?.try_into().ok()?,
+            ))
+        };
+        // Collections are loaded with their first font
+        let start = if self.data.starts_with(b"ttcf") {
+            read_u32(12)? as usize
+        } else {
+            0
+        };
+        let tables = read_u16(start + 4)? as usize;
+        let head = (0..tables)
+            .map(|table| start + 12 + table * 16)
+            .find(|&record| self.data.get(record..record + 4) == Some(b"head"))
+            .and_then(|record| read_u32(record + 8))? as usize;
+        let mut bbox = [0.0; 4];
+        for (i, edge) in bbox.iter_mut().enumerate() {
+            *edge = read_u16(head + 36 + 2 * i)? as i16 as f32;
+        }
+        Some(bbox)
+    }
+
+    fn advance_unscaled(&self, glyph: u16) -> f32 {
+        self.font.h_advance_unscaled(ab_glyph::GlyphId(glyph))
+    }
+
+    /// The width in millimeters of the given text with the given font size in points.
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let units = text
+            .chars()
+            .filter_map(|c| self.glyph(c))
+            .map(|glyph| self.advance_unscaled(glyph))
+            .sum::<f32>();
+        units / self.units_per_em() * size / document::PT_PER_MM
+    }
+
+    /// The height in millimeters of the part of the font above the baseline, with the given
+    /// font size in points.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.ascent_unscaled() / self.units_per_em() * size / document::PT_PER_MM
+    }
+}
+
+/// Settings of the native PDF album.
+#[derive(Clone)]
+pub struct PdfSettings {
+    pub page: PageSettings,
+    pub regular: Arc<PdfFont>,
+    pub bold: Arc<PdfFont>,
+    /// Where every chapter starts
+    pub chapter_break: PageBreak,
+    /// The photo book the album is printed as, if any
+    pub book: Option<BookFormat>,
+}
+
+/// A part of a photo container as it is laid out on the pages of the native PDF album.
+pub enum PrintBlock<'a> {
+    /// The title of a day or chapter
+    Heading(String),
+    /// A line of details below a heading, e.g. the route or the statistics of a day
+    Details(String),
+    /// Photos next to each other in one row, all in slots of the same width
+    Row(Vec<&'a Photo>),
+    /// The title of the photos above
+    Title(String),
+    /// The caption of the given photo below the photos, as plain text
+    Caption { text: String, photo: &'a Photo },
+}
+
+/// How a photo container is laid out on the pages of the native PDF album.
+/// The space the container needs is the height of all of its blocks.
+pub struct PrintLayout<'a> {
+    /// The blocks of the container, in the order they are placed one below the other
+    pub blocks: Vec<PrintBlock<'a>>,
+    /// Whether the rows of the container may be placed onto different pages. The title and the
+    /// caption always stay on the page of the last row.
+    pub splittable: bool,
+    /// Whether the container starts a new chapter, e.g. a day
+    pub chapter: bool,
+}
+
+impl<'a> PrintLayout<'a> {
+    /// The groups of blocks that must be placed onto the same page.
+    /// A container that may be split is split before every row except the first, so every row
+    /// keeps the blocks that follow it.
+    pub fn keep_together(&self) -> Vec<&[PrintBlock<'a>]> {
+        if !self.splittable {
+            return vec![&self.blocks[..]];
+        }
+        let mut groups = vec![];
+        let mut start = 0;
+        let mut after_row = false;
+        for (i, block) in self.blocks.iter().enumerate() {
+            if matches!(block, PrintBlock::Row(_)) {
+                if after_row {
+                    groups.push(&self.blocks[start..i]);
+                    start = i;
+                }
+                after_row = true;
+            }
+        }
+        groups.push(&self.blocks[start..]);
+        groups
+    }
+}
+
+/// Place the title of the album, the table of contents with the given entries if there are any,
+/// and the given containers.
+fn place_album(
+    layout: &mut PdfLayout,
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    contents: &[(String, usize)],
+) -> io::Result<()> {
+    layout.place(&PrintLayout {
+        blocks: vec![PrintBlock::Heading(title.to_string())],
+        splittable: false,
+        chapter: false,
+    })?;
+    if !contents.is_empty() {
+        layout.place(&PrintLayout {
+            blocks: vec![PrintBlock::Title(CONTENTS_TITLE.to_string())],
+            splittable: false,
+            chapter: false,
+        })?;
+        layout.place_contents(contents);
+    }
+    for container in containers {
+        layout.place(&container.print_layout())?;
+    }
+    Ok(())
+}
+
+/// Lay out the given containers onto pages and write the PDF album to the given path.
+/// With a table of contents, the album is laid out as draft first, to find the page of every
+/// day. For a photo book, the cover is written next to it, with the first photo of the album on
+/// the front cover. The files are only rewritten if their content changed.
+pub fn write_pdf(
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    settings: &PdfSettings,
+    table_of_contents: bool,
+    path: &Path,
+) -> io::Result<()> {
+    let mut contents = vec![];
+    if table_of_contents {
+        contents = containers
+            .iter()
+            .filter_map(|container| container.day_summary())
+            .map(|day| {
+                let text = [
+                    Some(day.title.to_string()),
+                    day.route(),
+                    day.distance.map(|distance| format!("{} km", distance)),
+                ]
+                .into_iter()
+                .flatten()
+                .join(" · ");
+                (text, 0)
+            })
+            .collect::<Vec<_>>();
+        let mut draft = PdfLayout::draft(settings);
+        place_album(&mut draft, containers, title, &contents)?;
+        for ((_, page), chapter_page) in contents.iter_mut().zip(draft.chapter_pages()) {
+            *page = *chapter_page;
+        }
+    }
+    let mut layout = PdfLayout::new(settings);
+    place_album(&mut layout, containers, title, &contents)?;
+    let pages = layout.page_count();
+    Album::write_if_changed(path, &layout.finish())?;
+    if let Some(book) = &settings.book {
+        let photo = containers
+            .iter()
+            .flat_map(|container| container.photos())
+            .next();
+        let cover = path.with_file_name("Album-cover.pdf");
+        book::write_cover(title, photo, settings, book, pages, &cover)?;
+    }
+    Ok(())
+}
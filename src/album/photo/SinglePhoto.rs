@@ -1,4 +1,10 @@
+use crate::album::model::{ContainerKind, ContainerModel};
+use crate::album::pdf::PrintLayout;
+use crate::album::photo::export_target::ExportTarget;
+use crate::album::photo::output_registry::OutputRegistry;
 use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::templates::{ContainerContext, Templates};
+use crate::album::typst;
 use std::io::{ErrorKind, Write};
 use std::path::Path;
 use std::{io, iter};
@@ -7,40 +13,41 @@ pub struct SinglePhoto {
     photo: Photo,
 }
 impl SinglePhoto {
+    /// Width of the slot of each photo in this container, as fraction of the page width
+    const SLOT_WIDTH: f32 = 1.0;
+    /// Displayed width of each photo in this container, as hint for the browser
+    const SIZES: &str = "100vw";
     pub fn new(photo: Photo) -> Self {
         Self { photo }
     }
 }
 
 impl PhotoContainer for SinglePhoto {
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> std::io::Result<()> {
-        writeln!(f, "<div class=\"imageblock fullsize\">")?;
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(
-            f,
-            "<div class=\"imagetext\">{}</div>",
-            self.photo.get_html_escaped_title()
+    fn print_markdown(
+        &self,
+        templates: &Templates,
+        index: usize,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let context = ContainerContext::new(
+            ContainerKind::SinglePhoto,
+            self.photos(),
+            Self::SIZES,
+            index,
         )?;
-        writeln!(f, "</div>")?;
-        writeln!(f, "</div>")?;
-        writeln!(f)?;
-        let caption = self.photo.get_html_escaped_caption();
-        if !caption.is_empty() {
-            writeln!(f, "<div class=\"textblock fullsizetext forimage\">")?;
-            writeln!(f)?;
-            writeln!(f, "{}", caption)?;
-            writeln!(f)?;
-            writeln!(f, "</div>")?;
-            writeln!(f)?;
-        }
-        Ok(())
+        templates.render_container(&context, f)
     }
 
-    fn write_to_directory(&self, target: &Path) -> io::Result<Box<dyn PhotoContainer>> {
-        match self.photo.write_to_directory(target) {
+    fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+    ) -> io::Result<Box<dyn PhotoContainer>> {
+        match self
+            .photo
+            .write_to_directory(target, export, registry, Self::SLOT_WIDTH)
+        {
             Ok(photo) => Ok(Box::new(SinglePhoto::new(photo))),
             Err(e) => Err(e),
         }
@@ -49,4 +56,25 @@ impl PhotoContainer for SinglePhoto {
     fn photos(&self) -> Box<dyn Iterator<Item = &Photo> + '_> {
         Box::new(iter::once(&self.photo))
     }
+
+    fn print_layout(&self) -> PrintLayout<'_> {
+        PrintLayout {
+            blocks: self.photo.print_blocks(vec![vec![&self.photo]]),
+            splittable: false,
+            chapter: false,
+        }
+    }
+
+    fn print_typst(&self, f: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            f,
+            "#single-photo({}, {})",
+            typst::photo(&self.photo)?,
+            typst::text_arguments(&self.photo)
+        )
+    }
+
+    fn model(&self) -> ContainerModel {
+        ContainerModel::new(ContainerKind::SinglePhoto, self.photos())
+    }
 }
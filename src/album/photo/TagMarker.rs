@@ -1,4 +1,10 @@
-use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::model::{ContainerKind, ContainerModel, DayModel};
+use crate::album::pdf::{PrintBlock, PrintLayout};
+use crate::album::photo::export_target::ExportTarget;
+use crate::album::photo::output_registry::OutputRegistry;
+use crate::album::photo::{DaySummary, Photo, PhotoContainer};
+use crate::album::templates::{ContainerContext, Templates};
+use crate::album::{typst, website};
 use itertools::Itertools;
 use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
@@ -17,8 +23,14 @@ pub struct TagMarker {
     dest_from: Option<String>,
     dest_to: Option<String>,
     title: String,
+    /// The anchor of the day header, which links to it from the table of contents
+    anchor: String,
 }
 impl TagMarker {
+    /// Width of the slot of each photo in this container, as fraction of the page width
+    const SLOT_WIDTH: f32 = 1.0;
+    /// Displayed width of each photo in this container, as hint for the browser
+    const SIZES: &str = "100vw";
     fn strip_unit(s: &str, u: &str) -> String {
         s.to_lowercase()
             .strip_suffix(u)
@@ -38,6 +50,7 @@ impl TagMarker {
             dest_from: None,
             dest_to: None,
             title: photo.get_html_escaped_title(),
+            anchor: website::slug(&photo.get_html_escaped_title()),
         };
         for line in photo
             .get_html_escaped_caption()
@@ -70,53 +83,43 @@ impl TagMarker {
         ret.photo = Some(photo); // TODO Read GPX File instead and show track using JavaScript?
         ret
     }
+
+    /// The anchor of the day header, derived from its title.
+    pub fn anchor(&self) -> &str {
+        &self.anchor
+    }
+
+    /// Use the given anchor for the day header, e.g. to keep it unique inside the album.
+    pub fn with_anchor(mut self, anchor: String) -> Self {
+        self.anchor = anchor;
+        self
+    }
 }
 
 impl PhotoContainer for TagMarker {
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> std::io::Result<()> {
-        writeln!(f, "<div class=\"dayheader\">")?;
-        writeln!(f)?;
-        writeln!(f, "## {}", self.title)?;
-        writeln!(f)?;
-        if let Some(dest_from) = &self.dest_from {
-            writeln!(f, "<div class=\"destfrom\">{}</div>", dest_from)?;
-        }
-        if let Some(dest_to) = &self.dest_to {
-            writeln!(f, "<div class=\"destto\">{}</div>", dest_to)?;
-        }
-        if let Some(ascent) = &self.ascent {
-            writeln!(f, "<div class=\"ascent\">{}</div>", ascent)?;
-        }
-        if let Some(descent) = &self.descent {
-            writeln!(f, "<div class=\"descent\">{}</div>", descent)?;
-        }
-        if let Some(distance) = &self.distance {
-            writeln!(f, "<div class=\"distance\">{}</div>", distance)?;
-        }
-        if let Some(moving_time) = &self.moving_time {
-            writeln!(f, "<div class=\"time\">{}</div>", moving_time)?;
-        }
-        if let Some(avg_speed) = &self.avg_speed {
-            writeln!(f, "<div class=\"speed\">{}</div>", avg_speed)?;
-        }
-        if let Some(photo) = &self.photo {
-            writeln!(f)?;
-            writeln!(f, "<div class=\"image\">")?;
-            writeln!(f)?;
-            photo.print_markdown(f)?;
-            writeln!(f)?;
-            writeln!(f, "</div>")?; //image
-        }
-        writeln!(f)?;
-        writeln!(f, "</div>")?; //dayheader
-        writeln!(f)?;
-        Ok(())
+    fn print_markdown(
+        &self,
+        templates: &Templates,
+        index: usize,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let context =
+            ContainerContext::new(ContainerKind::DayHeader, self.photos(), Self::SIZES, index)?
+                .with_day(self.model().day);
+        templates.render_container(&context, f)
     }
 
-    fn write_to_directory(&self, target: &Path) -> io::Result<Box<dyn PhotoContainer>> {
+    fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+    ) -> io::Result<Box<dyn PhotoContainer>> {
         if let Some(photo) = &self.photo {
-            match photo.write_to_directory(target) {
-                Ok(photo) => Ok(Box::new(TagMarker::new(photo))),
+            match photo.write_to_directory(target, export, registry, Self::SLOT_WIDTH) {
+                Ok(photo) => Ok(Box::new(
+                    TagMarker::new(photo).with_anchor(self.anchor.clone()),
+                )),
                 Err(e) => Err(e),
             }
         } else {
@@ -131,4 +134,88 @@ impl PhotoContainer for TagMarker {
             Box::new(iter::empty())
         }
     }
+
+    fn print_layout(&self) -> PrintLayout<'_> {
+        let mut blocks = vec![PrintBlock::Heading(self.title.clone())];
+        let route = [&self.dest_from, &self.dest_to]
+            .into_iter()
+            .flatten()
+            .join(" → ");
+        if !route.is_empty() {
+            blocks.push(PrintBlock::Details(format!("↪ {}", route)));
+        }
+        let stats = [
+            self.distance.as_ref().map(|d| format!("⇄ {} km", d)),
+            self.ascent.as_ref().map(|a| format!("↗ {} m", a)),
+            self.descent.as_ref().map(|d| format!("↘ {} m", d)),
+            // The timer symbol of the HTML album is missing from common fonts
+            self.moving_time.as_ref().map(|t| format!("◷ {}", t)),
+            self.avg_speed.as_ref().map(|s| format!("⌀ {} km/h", s)),
+        ]
+        .into_iter()
+        .flatten()
+        .join("   ");
+        if !stats.is_empty() {
+            blocks.push(PrintBlock::Details(stats));
+        }
+        if let Some(photo) = &self.photo {
+            blocks.push(PrintBlock::Row(vec![photo]));
+        }
+        // The day header stays together with the photo of the day
+        PrintLayout {
+            blocks,
+            splittable: false,
+            chapter: true,
+        }
+    }
+
+    fn print_typst(&self, f: &mut dyn Write) -> io::Result<()> {
+        let optional =
+            |value: &Option<String>| value.as_deref().map_or("none".to_string(), typst::string);
+        write!(f, "#day-header(title: {}", typst::string(&self.title))?;
+        for (name, value) in [
+            ("from", &self.dest_from),
+            ("to", &self.dest_to),
+            ("distance", &self.distance),
+            ("ascent", &self.ascent),
+            ("descent", &self.descent),
+            ("moving-time", &self.moving_time),
+            ("avg-speed", &self.avg_speed),
+        ] {
+            write!(f, ", {}: {}", name, optional(value))?;
+        }
+        if let Some(photo) = &self.photo {
+            write!(f, ", photo: {}", typst::photo(photo)?)?;
+        }
+        writeln!(f, ")")
+    }
+
+    fn model(&self) -> ContainerModel {
+        ContainerModel {
+            day: Some(DayModel {
+                title: self.title.clone(),
+                anchor: self.anchor.clone(),
+                from: self.dest_from.clone(),
+                to: self.dest_to.clone(),
+                distance: self.distance.clone(),
+                ascent: self.ascent.clone(),
+                descent: self.descent.clone(),
+                moving_time: self.moving_time.clone(),
+                avg_speed: self.avg_speed.clone(),
+                gpx: self.gpxfile.clone(),
+            }),
+            ..ContainerModel::new(ContainerKind::DayHeader, self.photos())
+        }
+    }
+
+    fn day_summary(&self) -> Option<DaySummary<'_>> {
+        Some(DaySummary {
+            title: &self.title,
+            anchor: &self.anchor,
+            from: self.dest_from.as_deref(),
+            to: self.dest_to.as_deref(),
+            distance: self.distance.as_deref(),
+            photo: self.photo.as_ref(),
+        })
+    }
 }
@@ -1,4 +1,10 @@
+use crate::album::model::{ContainerKind, ContainerModel};
+use crate::album::pdf::PrintLayout;
+use crate::album::photo::export_target::ExportTarget;
+use crate::album::photo::output_registry::OutputRegistry;
 use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::templates::{ContainerContext, Templates};
+use crate::album::typst;
 use std::io::Write;
 use std::path::Path;
 use std::{io, iter};
@@ -9,6 +15,10 @@ pub struct ThreePhotos {
     photo3: Photo,
 }
 impl ThreePhotos {
+    /// Width of the slot of each photo in this container, as fraction of the page width
+    const SLOT_WIDTH: f32 = 1.0 / 3.0;
+    /// Displayed width of each photo in this container, as hint for the browser
+    const SIZES: &str = "33vw";
     pub fn new(photo1: Photo, photo2: Photo, photo3: Photo) -> Self {
         Self {
             photo1,
@@ -19,60 +29,35 @@ impl ThreePhotos {
 }
 
 impl PhotoContainer for ThreePhotos {
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> std::io::Result<()> {
-        writeln!(f, "<div class=\"imageblock multirow threeimages\">")?;
-        writeln!(f, "<div class=\"imagerow\">")?;
-        // Image 1
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo1.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(f, "</div>")?; // image
-                                // Image 2
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo2.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(f, "</div>")?; // image
-                                // Image 3
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo3.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(f, "</div>")?; // image
-
-        writeln!(f, "</div>")?; // imagerow
-
-        writeln!(
-            f,
-            "<div class=\"imagetext\">{}</div>",
-            self.photo3.get_html_escaped_title()
+    fn print_markdown(
+        &self,
+        templates: &Templates,
+        index: usize,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let context = ContainerContext::new(
+            ContainerKind::ThreePhotos,
+            self.photos(),
+            Self::SIZES,
+            index,
         )?;
-        writeln!(f, "</div>")?; //imageblock
-        writeln!(f)?;
-        let caption = self.photo3.get_html_escaped_caption();
-        if !caption.is_empty() {
-            writeln!(f, "<div class=\"textblock fullsizetext forimage\">")?;
-            writeln!(f)?;
-            writeln!(f, "{}", caption)?;
-            writeln!(f)?;
-            writeln!(f, "</div>")?;
-            writeln!(f)?;
-        }
-        Ok(())
+        templates.render_container(&context, f)
     }
 
-    fn write_to_directory(&self, target: &Path) -> io::Result<Box<dyn PhotoContainer>> {
-        match self.photo1.write_to_directory(target) {
-            Ok(photo1) => match self.photo2.write_to_directory(target) {
-                Ok(photo2) => match self.photo3.write_to_directory(target) {
-                    Ok(photo3) => Ok(Box::new(ThreePhotos::new(photo1, photo2, photo3))),
-                    Err(e) => Err(e),
-                },
-                Err(e) => Err(e),
-            },
-            Err(e) => Err(e),
-        }
+    fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+    ) -> io::Result<Box<dyn PhotoContainer>> {
+        Ok(Box::new(ThreePhotos::new(
+            self.photo1
+                .write_to_directory(target, export, registry, Self::SLOT_WIDTH)?,
+            self.photo2
+                .write_to_directory(target, export, registry, Self::SLOT_WIDTH)?,
+            self.photo3
+                .write_to_directory(target, export, registry, Self::SLOT_WIDTH)?,
+        )))
     }
 
     fn photos(&self) -> Box<dyn Iterator<Item = &Photo> + '_> {
@@ -82,4 +67,29 @@ impl PhotoContainer for ThreePhotos {
                 .chain(iter::once(&self.photo3)),
         )
     }
+
+    fn print_layout(&self) -> PrintLayout<'_> {
+        PrintLayout {
+            blocks: self
+                .photo3
+                .print_blocks(vec![vec![&self.photo1, &self.photo2, &self.photo3]]),
+            splittable: false,
+            chapter: false,
+        }
+    }
+
+    fn print_typst(&self, f: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            f,
+            "#three-photos({}, {}, {}, {})",
+            typst::photo(&self.photo1)?,
+            typst::photo(&self.photo2)?,
+            typst::photo(&self.photo3)?,
+            typst::text_arguments(&self.photo3)
+        )
+    }
+
+    fn model(&self) -> ContainerModel {
+        ContainerModel::new(ContainerKind::ThreePhotos, self.photos())
+    }
 }
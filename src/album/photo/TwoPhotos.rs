@@ -1,4 +1,10 @@
+use crate::album::model::{ContainerKind, ContainerModel};
+use crate::album::pdf::PrintLayout;
+use crate::album::photo::export_target::ExportTarget;
+use crate::album::photo::output_registry::OutputRegistry;
 use crate::album::photo::{Photo, PhotoContainer};
+use crate::album::templates::{ContainerContext, Templates};
+use crate::album::typst;
 use std::io::{ErrorKind, Write};
 use std::path::Path;
 use std::{io, iter};
@@ -8,59 +14,66 @@ pub struct TwoPhotos {
     photo2: Photo,
 }
 impl TwoPhotos {
+    /// Width of the slot of each photo in this container, as fraction of the page width
+    const SLOT_WIDTH: f32 = 1.0 / 2.0;
+    /// Displayed width of each photo in this container, as hint for the browser
+    const SIZES: &str = "50vw";
     pub fn new(photo1: Photo, photo2: Photo) -> Self {
         Self { photo1, photo2 }
     }
 }
 
 impl PhotoContainer for TwoPhotos {
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> std::io::Result<()> {
-        writeln!(f, "<div class=\"imageblock multirow twoimages\">")?;
-        writeln!(f, "<div class=\"imagerow\">")?;
-        // Image 1
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo1.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(f, "</div>")?; // image
-                                // Image 2
-        writeln!(f, "<div class=\"image\">")?;
-        writeln!(f)?;
-        self.photo2.print_markdown(f)?;
-        writeln!(f)?;
-        writeln!(f, "</div>")?; // image
-        writeln!(f, "</div>")?; // imagerow
-
-        writeln!(
-            f,
-            "<div class=\"imagetext\">{}</div>",
-            self.photo2.get_html_escaped_title()
-        )?;
-        writeln!(f, "</div>")?; //imageblock
-        writeln!(f)?;
-        let caption = self.photo2.get_html_escaped_caption();
-        if !caption.is_empty() {
-            writeln!(f, "<div class=\"textblock fullsizetext forimage\">")?;
-            writeln!(f)?;
-            writeln!(f, "{}", caption)?;
-            writeln!(f)?;
-            writeln!(f, "</div>")?;
-            writeln!(f)?;
-        }
-        Ok(())
+    fn print_markdown(
+        &self,
+        templates: &Templates,
+        index: usize,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let context =
+            ContainerContext::new(ContainerKind::TwoPhotos, self.photos(), Self::SIZES, index)?;
+        templates.render_container(&context, f)
     }
 
-    fn write_to_directory(&self, target: &Path) -> io::Result<Box<dyn PhotoContainer>> {
-        match self.photo1.write_to_directory(target) {
-            Ok(photo1) => match self.photo2.write_to_directory(target) {
-                Ok(photo2) => Ok(Box::new(TwoPhotos::new(photo1, photo2))),
-                Err(e) => Err(e),
-            },
-            Err(e) => Err(e),
-        }
+    fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+    ) -> io::Result<Box<dyn PhotoContainer>> {
+        Ok(Box::new(TwoPhotos::new(
+            self.photo1
+                .write_to_directory(target, export, registry, Self::SLOT_WIDTH)?,
+            self.photo2
+                .write_to_directory(target, export, registry, Self::SLOT_WIDTH)?,
+        )))
     }
 
     fn photos(&self) -> Box<dyn Iterator<Item = &Photo> + '_> {
         Box::new(iter::once(&self.photo1).chain(iter::once(&self.photo2)))
     }
+
+    fn print_layout(&self) -> PrintLayout<'_> {
+        PrintLayout {
+            blocks: self
+                .photo2
+                .print_blocks(vec![vec![&self.photo1, &self.photo2]]),
+            splittable: false,
+            chapter: false,
+        }
+    }
+
+    fn print_typst(&self, f: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            f,
+            "#two-photos({}, {}, {})",
+            typst::photo(&self.photo1)?,
+            typst::photo(&self.photo2)?,
+            typst::text_arguments(&self.photo2)
+        )
+    }
+
+    fn model(&self) -> ContainerModel {
+        ContainerModel::new(ContainerKind::TwoPhotos, self.photos())
+    }
 }
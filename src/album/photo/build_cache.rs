@@ -0,0 +1,393 @@
+use crate::album::photo::{Photo, PhotoLoadingError};
+use itertools::Itertools;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fs, mem};
+
+/// Name of the cache file inside the output directory.
+pub const CACHE_FILE: &str = ".radalbum-cache.json";
+
+/// Version of the cache file format. Caches with another version are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// Everything that is known about one source photo from an earlier album run.
+#[derive(Clone, Serialize, Deserialize)]
+struct SourceEntry {
+    /// Modification time in seconds and nanoseconds since the Unix epoch
+    modified: (u64, u32),
+    size: u64,
+    /// Hex-encoded SHA-256 hash of the file content, if it has been computed
+    hash: Option<String>,
+    heading: String,
+    description: String,
+    copyright: Option<String>,
+}
+
+/// The content of the cache file.
+#[derive(Default, Serialize, Deserialize)]
+struct CacheData {
+    version: u32,
+    /// Source photos by their path
+    sources: BTreeMap<PathBuf, SourceEntry>,
+    /// Written photos, relative to the output directory, by the hash of their source and the
+    /// variant they were written with, and written documents by their path
+    artifacts: BTreeMap<String, PathBuf>,
+}
+
+/// A cache of source photo metadata, content hashes and written photos, which is stored in the
+/// output directory.
+/// Source photos whose path, modification time and size did not change since the last run are
+/// not read again, and photos that have already been written in the same variant are not
+/// written again.
+#[derive(Default)]
+pub struct BuildCache {
+    /// The output directory the cache belongs to
+    directory: PathBuf,
+    /// The cache written by the previous run
+    previous: CacheData,
+    /// The cache of the current run, which only contains the entries used in this run
+    current: CacheData,
+    /// Whether all photos are read and written again, ignoring the entries of the previous run.
+    /// The previous run is still used to delete its stale photos.
+    force: bool,
+}
+
+impl BuildCache {
+    /// An empty cache for the given output directory, which causes every photo to be read and
+    /// written again.
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            ..Self::default()
+        }
+    }
+
+    /// Load the cache from the given output directory.
+    /// If there is no cache yet, or it cannot be read, an empty cache is returned.
+    pub fn load(directory: &Path) -> Self {
+        let path = directory.join(CACHE_FILE);
+        if !path.is_file() {
+            return Self::new(directory);
+        }
+        let previous = File::open(&path).map_err(|e| e.to_string()).and_then(|f| {
+            serde_json::from_reader::<_, CacheData>(BufReader::new(f)).map_err(|e| e.to_string())
+        });
+        match previous {
+            Ok(previous) if previous.version == CACHE_VERSION => Self {
+                directory: directory.to_path_buf(),
+                previous,
+                current: CacheData::default(),
+                force: false,
+            },
+            Ok(_) => Self::new(directory),
+            Err(e) => {
+                eprintln!("Ignoring unreadable build cache {:?}: {}", path, e);
+                Self::new(directory)
+            },
+        }
+    }
+
+    /// The cache with all entries of the previous run ignored if `force` is set, so that every
+    /// photo is read and written again, while stale photos of the previous run are still deleted.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    fn hex(hash: &[u8; 32]) -> String {
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn artifact_key(hash: &[u8; 32], variant: &str) -> String {
+        format!("{} {}", Self::hex(hash), variant)
+    }
+
+    /// Read the modification time and size of the given file.
+    fn stat(path: &Path) -> io::Result<((u64, u32), u64)> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Ok((
+            (modified.as_secs(), modified.subsec_nanos()),
+            metadata.len(),
+        ))
+    }
+
+    /// Look up the entry of the given source file from this or the previous run, if the file did
+    /// not change in between.
+    fn unchanged_entry(&self, source: &Path) -> io::Result<Option<SourceEntry>> {
+        if let Some(entry) = self.current.sources.get(source) {
+            return Ok(Some(entry.clone()));
+        }
+        if self.force {
+            return Ok(None);
+        }
+        let (modified, size) = Self::stat(source)?;
+        Ok(self
+            .previous
+            .sources
+            .get(source)
+            .filter(|entry| entry.modified == modified && entry.size == size)
+            .cloned())
+    }
+
+    /// Read the metadata of the photo at the given path, or use the cached metadata if the file
+    /// did not change.
+    fn read_entry(&self, source: &Path) -> Result<SourceEntry, PhotoLoadingError> {
+        if let Some(entry) = self.unchanged_entry(source)? {
+            return Ok(entry);
+        }
+        println!("Loading Photo {:?}", source);
+        let (modified, size) = Self::stat(source)?;
+        let photo = Photo::load_from_disk(source.to_path_buf())?;
+        Ok(SourceEntry {
+            modified,
+            size,
+            hash: None,
+            heading: photo.heading,
+            description: photo.description,
+            copyright: photo.copyright,
+        })
+    }
+
+    /// Load the photos at the given paths, using the cached metadata of all files that did not
+    /// change.
+    /// The metadata of the other photos is read in parallel. The results are returned in the
+    /// order of the given paths.
+    pub fn load_photos(&mut self, sources: Vec<PathBuf>) -> Vec<Result<Photo, PhotoLoadingError>> {
+        let entries = sources
+            .par_iter()
+            .map(|source| self.read_entry(source))
+            .collect::<Vec<_>>();
+        sources
+            .into_iter()
+            .zip(entries)
+            .map(|(source, entry)| {
+                let entry = entry?;
+                let photo = Photo {
+                    heading: entry.heading.clone(),
+                    description: entry.description.clone(),
+                    source: source.clone(),
+                    copyright: entry.copyright.clone(),
+                    size: None,
+                    renditions: vec![],
+                };
+                self.current.sources.insert(source, entry);
+                Ok(photo)
+            })
+            .collect()
+    }
+
+    fn hash_file(source: &Path) -> io::Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(source)?, &mut hasher)?;
+        Ok(hasher.finalize().into())
+    }
+
+    /// Compute the hashes of all given source files that are not cached yet, in parallel, so
+    /// that [Self::source_hash] does not need to read them one after another.
+    pub fn hash_sources(&mut self, sources: &[&Path]) -> io::Result<()> {
+        let missing = sources
+            .iter()
+            .copied()
+            .filter(|source| {
+                self.current
+                    .sources
+                    .get(*source)
+                    .is_some_and(|entry| entry.hash.is_none())
+            })
+            .unique()
+            .collect::<Vec<_>>();
+        let hashes = missing
+            .par_iter()
+            .map(|source| Self::hash_file(source))
+            .collect::<io::Result<Vec<_>>>()?;
+        for (source, hash) in missing.into_iter().zip(hashes) {
+            if let Some(entry) = self.current.sources.get_mut(source) {
+                entry.hash = Some(Self::hex(&hash));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compute the SHA-256 hash of the given source file's content, or use the cached hash if the
+    /// file did not change.
+    pub fn source_hash(&mut self, source: &Path) -> io::Result<[u8; 32]> {
+        let entry = self.unchanged_entry(source)?;
+        if let Some(hash) = entry.as_ref().and_then(|entry| entry.hash.as_ref()) {
+            let mut bytes = [0u8; 32];
+            if hash.len() == 64 {
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = u8::from_str_radix(&hash[2 * i..2 * i + 2], 16)
+                        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                }
+                return Ok(bytes);
+            }
+        }
+        let hash = Self::hash_file(source)?;
+        if let Some(mut entry) = entry {
+            entry.hash = Some(Self::hex(&hash));
+            self.current.sources.insert(source.to_path_buf(), entry);
+        }
+        Ok(hash)
+    }
+
+    /// Look up the file written in the previous run for the given source hash and variant, if it
+    /// still exists.
+    pub fn previous_artifact(&self, hash: &[u8; 32], variant: &str) -> Option<PathBuf> {
+        if self.force {
+            return None;
+        }
+        self.previous
+            .artifacts
+            .get(&Self::artifact_key(hash, variant))
+            .map(|path| self.directory.join(path))
+            .filter(|path| path.is_file())
+    }
+
+    /// Remember that the given file inside the output directory has been written for the given
+    /// source hash and variant.
+    pub fn record_artifact(&mut self, hash: &[u8; 32], variant: &str, path: &Path) {
+        if let Ok(path) = path.strip_prefix(&self.directory) {
+            self.current
+                .artifacts
+                .insert(Self::artifact_key(hash, variant), path.to_path_buf());
+        }
+    }
+
+    /// Remember that the given document inside the output directory, e.g. a page of the website,
+    /// has been written during this run, so that it is deleted once it is not written anymore.
+    pub fn record_document(&mut self, path: &Path) {
+        if let Ok(path) = path.strip_prefix(&self.directory) {
+            self.current
+                .artifacts
+                .insert(format!("document {}", path.display()), path.to_path_buf());
+        }
+    }
+
+    /// Forget the given written file, e.g. because writing it failed.
+    pub fn discard_artifact(&mut self, path: &Path) {
+        self.current
+            .artifacts
+            .retain(|_, artifact| self.directory.join(artifact) != path);
+    }
+
+    /// Save the cache of this run into the output directory.
+    /// Photos and documents written by the previous run that are not used anymore are deleted.
+    pub fn save(&mut self) -> io::Result<()> {
+        let previous = mem::take(&mut self.previous);
+        for stale in previous.artifacts.values() {
+            let path = self.directory.join(stale);
+            if !self.current.artifacts.values().any(|used| used == stale) && path.is_file() {
+                fs::remove_file(path)?;
+            }
+        }
+        self.current.version = CACHE_VERSION;
+        let out = BufWriter::new(File::create(self.directory.join(CACHE_FILE))?);
+        serde_json::to_writer_pretty(out, &self.current).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new temporary directory for the given test.
+    fn temp_dir(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("radalbum-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Write the given source file and remember it as loaded by the given cache.
+    fn add_source(cache: &mut BuildCache, path: &Path, content: &str) {
+        fs::write(path, content).unwrap();
+        let (modified, size) = BuildCache::stat(path).unwrap();
+        let entry = SourceEntry {
+            modified,
+            size,
+            hash: None,
+            heading: "Title".to_string(),
+            description: "Caption".to_string(),
+            copyright: None,
+        };
+        cache.current.sources.insert(path.to_path_buf(), entry);
+    }
+
+    #[test]
+    fn unchanged_sources_and_artifacts_are_reused() {
+        let directory = temp_dir("cache-reuse");
+        let source = directory.join("photo.jpg");
+        let out = directory.join("out");
+        fs::create_dir_all(&out).unwrap();
+        let mut cache = BuildCache::load(&out);
+        add_source(&mut cache, &source, "photo");
+        let hash = cache.source_hash(&source).unwrap();
+        let artifact = out.join("photo.jpg");
+        fs::write(&artifact, "written").unwrap();
+        cache.record_artifact(&hash, "l480", &artifact);
+        cache.save().unwrap();
+
+        let cache = BuildCache::load(&out);
+        let entry = cache.unchanged_entry(&source).unwrap().unwrap();
+        assert_eq!(entry.heading, "Title");
+        assert_eq!(entry.hash, Some(BuildCache::hex(&hash)));
+        assert_eq!(cache.previous_artifact(&hash, "l480"), Some(artifact));
+        assert_eq!(cache.previous_artifact(&hash, "l960"), None);
+    }
+
+    #[test]
+    fn changed_sources_are_read_again() {
+        let directory = temp_dir("cache-changed");
+        let source = directory.join("photo.jpg");
+        let mut cache = BuildCache::load(&directory);
+        add_source(&mut cache, &source, "photo");
+        cache.save().unwrap();
+
+        fs::write(&source, "edited photo").unwrap();
+        let mut cache = BuildCache::load(&directory);
+        assert!(cache.unchanged_entry(&source).unwrap().is_none());
+        let mut hasher = Sha256::new();
+        hasher.update(b"edited photo");
+        let expected: [u8; 32] = hasher.finalize().into();
+        assert_eq!(cache.source_hash(&source).unwrap(), expected);
+    }
+
+    #[test]
+    fn stale_artifacts_are_deleted() {
+        let directory = temp_dir("cache-stale");
+        let mut cache = BuildCache::load(&directory);
+        let (kept, stale) = (directory.join("kept.jpg"), directory.join("stale.jpg"));
+        fs::write(&kept, "kept").unwrap();
+        fs::write(&stale, "stale").unwrap();
+        cache.record_artifact(&[1; 32], "", &kept);
+        cache.record_artifact(&[2; 32], "", &stale);
+        cache.save().unwrap();
+
+        let mut cache = BuildCache::load(&directory);
+        cache.record_artifact(&[1; 32], "", &kept);
+        cache.save().unwrap();
+        assert!(kept.is_file());
+        assert!(!stale.exists());
+    }
+
+    #[test]
+    fn unreadable_cache_is_ignored() {
+        let directory = temp_dir("cache-unreadable");
+        fs::write(directory.join(CACHE_FILE), "not json").unwrap();
+        let cache = BuildCache::load(&directory);
+        assert!(cache.previous.sources.is_empty());
+        assert!(cache.previous.artifacts.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::album::assets::AssetFile;
+use crate::album::pdf::PdfSettings;
+use crate::album::photo::image_processing::ImageProfile;
+use crate::album::photo::metadata_policy::PrivacySettings;
+use crate::album::photo::watermark::Watermark;
+use clap::ValueEnum;
+
+/// The kinds of export targets.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TargetKind {
+    /// The HTML album
+    Web,
+    /// The PDF album
+    Print,
+    /// The EPUB album
+    Ebook,
+}
+
+/// One output format of the album, e.g. the web album or the printed album.
+/// Every target gets its own Markdown file, which references the copies of the photos that have
+/// been processed for this target.
+pub struct ExportTarget {
+    pub kind: TargetKind,
+    /// File name of the Markdown file written for this target
+    pub markdown_file: &'static str,
+    /// Name of the subfolder of the output directory that the photos of this target are
+    /// written to
+    pub image_dir: &'static str,
+    /// How the photos are resized and re-encoded for this target.
+    /// If this is none, the original files are copied.
+    pub image_profile: Option<ImageProfile>,
+    /// Widths in pixels of the additional, smaller copies of each photo that are offered to the
+    /// browser in a srcset. Only used if there is an image profile.
+    pub srcset_widths: Vec<u32>,
+    /// Which metadata of the source photos is kept in the written photos
+    pub privacy: PrivacySettings,
+    /// The watermark drawn onto the photos of this target, if any.
+    /// Watermarks are only drawn if the photos are processed with an image profile.
+    pub watermark: Option<Watermark>,
+    /// Settings of the native PDF album. If this is none, the PDF album is built from the
+    /// Markdown file with pandoc and wkhtmltopdf instead. Only used by the print target.
+    pub pdf: Option<PdfSettings>,
+    /// Document class of the LaTeX album. If this is none, no LaTeX album is written.
+    /// Only used by the print target.
+    pub latex_class: Option<AssetFile>,
+    /// Template of the Typst album. If this is none, no Typst album is written.
+    /// Only used by the print target.
+    pub typst_template: Option<AssetFile>,
+    /// Whether the multi-page website with a page per day is written.
+    /// Only used by the web target.
+    pub website: bool,
+    /// Whether a table of contents of all days is written after the album title
+    pub table_of_contents: bool,
+}
+
+impl ExportTarget {
+    /// The target for the HTML album.
+    pub fn web(
+        image_profile: Option<ImageProfile>,
+        srcset_widths: Vec<u32>,
+        privacy: PrivacySettings,
+    ) -> Self {
+        Self {
+            kind: TargetKind::Web,
+            markdown_file: "Album.md",
+            image_dir: "img",
+            image_profile,
+            srcset_widths,
+            privacy,
+            watermark: None,
+            pdf: None,
+            latex_class: None,
+            typst_template: None,
+            website: false,
+            table_of_contents: false,
+        }
+    }
+
+    /// The target for the PDF album.
+    /// If the original photos are used, they are shared with the web target.
+    pub fn print(image_profile: Option<ImageProfile>, privacy: PrivacySettings) -> Self {
+        Self {
+            kind: TargetKind::Print,
+            markdown_file: "Album-print.md",
+            image_dir: if image_profile.is_some() {
+                "img-print"
+            } else {
+                "img"
+            },
+            image_profile,
+            srcset_widths: vec![],
+            privacy,
+            watermark: None,
+            pdf: None,
+            latex_class: None,
+            typst_template: None,
+            website: false,
+            table_of_contents: false,
+        }
+    }
+
+    /// The target for the EPUB album.
+    pub fn ebook(image_profile: Option<ImageProfile>, privacy: PrivacySettings) -> Self {
+        Self {
+            kind: TargetKind::Ebook,
+            markdown_file: "Album-ebook.md",
+            image_dir: if image_profile.is_some() {
+                "img-ebook"
+            } else {
+                "img"
+            },
+            image_profile,
+            srcset_widths: vec![],
+            privacy,
+            watermark: None,
+            pdf: None,
+            latex_class: None,
+            typst_template: None,
+            website: false,
+            table_of_contents: false,
+        }
+    }
+
+    /// Draw the given watermark onto the photos of this target.
+    pub fn with_watermark(mut self, watermark: Option<Watermark>) -> Self {
+        self.watermark = watermark;
+        self
+    }
+
+    /// Write the LaTeX album of this target with the given document class.
+    pub fn with_latex_class(mut self, latex_class: Option<AssetFile>) -> Self {
+        self.latex_class = latex_class;
+        self
+    }
+
+    /// Write the Typst album of this target with the given template.
+    pub fn with_typst_template(mut self, typst_template: Option<AssetFile>) -> Self {
+        self.typst_template = typst_template;
+        self
+    }
+
+    /// Write the website of this target, with an index page and a page per day.
+    pub fn with_website(mut self, website: bool) -> Self {
+        self.website = website;
+        self
+    }
+
+    /// Write a table of contents of all days after the album title.
+    pub fn with_table_of_contents(mut self, table_of_contents: bool) -> Self {
+        self.table_of_contents = table_of_contents;
+        self
+    }
+
+    /// Lay out the PDF album of this target natively with the given settings.
+    pub fn with_pdf(mut self, pdf: Option<PdfSettings>) -> Self {
+        self.pdf = pdf;
+        self
+    }
+}
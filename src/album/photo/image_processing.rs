@@ -0,0 +1,226 @@
+use crate::album::photo::watermark::Watermark;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use jpeg_encoder::{ColorType, Density, Encoder};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, ErrorKind};
+use std::path::Path;
+
+/// Millimeters per inch, used to convert printed sizes into pixels.
+const MM_PER_INCH: f32 = 25.4;
+
+/// How large the processed images of an output target should be.
+#[derive(Clone, PartialEq)]
+pub enum ImageSize {
+    /// Limit the longer edge of every image to the given number of pixels.
+    LongEdge(u32),
+    /// Limit the width of every image to the given number of pixels.
+    Width(u32),
+    /// Size every image to the printed width of its slot on a page of the given width,
+    /// at the given resolution.
+    Print { dpi: u32, page_width_mm: f32 },
+}
+
+/// Settings for resizing and re-encoding the photos of one output target.
+/// Images are never scaled up. JPEG images are re-encoded as JPEG, all other images as PNG.
+#[derive(Clone, PartialEq)]
+pub struct ImageProfile {
+    pub size: ImageSize,
+    /// JPEG quality from 1 to 100
+    pub jpeg_quality: u8,
+    /// Write progressive JPEG images, which show a preview while loading in the browser
+    pub progressive: bool,
+    /// Sigma and threshold of an unsharp mask applied after resizing
+    pub sharpen: Option<(f32, i32)>,
+}
+
+impl ImageProfile {
+    /// The default profile for web output, with the given maximum long edge and JPEG quality.
+    pub fn web(long_edge: u32, jpeg_quality: u8) -> Self {
+        Self {
+            size: ImageSize::LongEdge(long_edge),
+            jpeg_quality,
+            progressive: true,
+            sharpen: Some((0.5, 2)),
+        }
+    }
+
+    /// The default profile for print output, with the given resolution and JPEG quality, for
+    /// photos printed across the given width of the page in millimeters.
+    pub fn print(dpi: u32, jpeg_quality: u8, page_width_mm: f32) -> Self {
+        Self {
+            size: ImageSize::Print { dpi, page_width_mm },
+            jpeg_quality,
+            progressive: false,
+            sharpen: None,
+        }
+    }
+
+    /// A copy of this profile that produces images of the given size.
+    pub fn with_size(&self, size: ImageSize) -> Self {
+        Self {
+            size,
+            ..self.clone()
+        }
+    }
+
+    /// The maximum width in pixels of an image printed into a slot with the given width,
+    /// as fraction of the page width.
+    fn print_width(dpi: u32, page_width_mm: f32, slot_width: f32) -> u32 {
+        (page_width_mm * slot_width / MM_PER_INCH * dpi as f32).round() as u32
+    }
+
+    /// A short description of the processing done for a slot with the given width.
+    /// Two images with the same content and the same variant result in the same output file.
+    pub fn variant(&self, slot_width: f32) -> String {
+        let size = match self.size {
+            ImageSize::LongEdge(long_edge) => format!("l{}", long_edge),
+            ImageSize::Width(width) => format!("x{}", width),
+            ImageSize::Print { dpi, page_width_mm } => {
+                format!("w{}", Self::print_width(dpi, page_width_mm, slot_width))
+            },
+        };
+        format!(
+            "{}q{}p{}s{:?}",
+            size, self.jpeg_quality, self.progressive, self.sharpen
+        )
+    }
+
+    /// Compute the output size of an image with the given size in a slot with the given width.
+    fn target_size(&self, width: u32, height: u32, slot_width: f32) -> (u32, u32) {
+        let scale = match self.size {
+            ImageSize::LongEdge(long_edge) => long_edge as f32 / width.max(height) as f32,
+            ImageSize::Width(max_width) => max_width as f32 / width as f32,
+            ImageSize::Print { dpi, page_width_mm } => {
+                Self::print_width(dpi, page_width_mm, slot_width) as f32 / width as f32
+            },
+        };
+        if scale >= 1.0 {
+            return (width, height);
+        }
+        (
+            ((width as f32 * scale).round() as u32).max(1),
+            ((height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    /// Compute the size of the image at `source` after it has been processed for a slot with the
+    /// given width, without processing it.
+    pub fn output_size(&self, source: &Path, slot_width: f32) -> io::Result<(u32, u32)> {
+        let (width, height) = displayed_size(source)?;
+        Ok(self.target_size(width, height, slot_width))
+    }
+
+    /// Read the image at `source`, resize it for a slot with the given width (as fraction of the
+    /// page width), and write it to `dest`.
+    /// If a watermark is given, it is drawn onto the resized image, using the given copyright
+    /// notice for text watermarks without explicit text.
+    /// The EXIF orientation is applied to the pixels and the ICC color profile is kept if it
+    /// describes RGB colors.
+    pub fn process(
+        &self,
+        source: &Path,
+        dest: &Path,
+        slot_width: f32,
+        watermark: Option<&Watermark>,
+        copyright: Option<&str>,
+    ) -> io::Result<()> {
+        let reader = ImageReader::open(source)?.with_guessed_format()?;
+        let format = reader.format();
+        let mut decoder = reader.into_decoder().map_err(io::Error::other)?;
+        let orientation = decoder.orientation().map_err(io::Error::other)?;
+        let icc_profile = decoder.icc_profile().map_err(io::Error::other)?;
+        let mut image = DynamicImage::from_decoder(decoder).map_err(io::Error::other)?;
+        image.apply_orientation(orientation);
+
+        let (width, height) = self.target_size(image.width(), image.height(), slot_width);
+        if (width, height) != (image.width(), image.height()) {
+            image = image.resize_exact(width, height, FilterType::Lanczos3);
+        }
+        if let Some((sigma, threshold)) = self.sharpen {
+            image = image.unsharpen(sigma, threshold);
+        }
+        if let Some(watermark) = watermark {
+            watermark.apply(&mut image, copyright);
+        }
+
+        if format != Some(ImageFormat::Jpeg) {
+            return image
+                .save_with_format(dest, ImageFormat::Png)
+                .map_err(io::Error::other);
+        }
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is {}x{} pixels, but JPEG files can be at most {} pixels wide and high",
+                    source.display(),
+                    width,
+                    height,
+                    u16::MAX
+                ),
+            ));
+        };
+        let mut encoder = Encoder::new(BufWriter::new(File::create(dest)?), self.jpeg_quality);
+        encoder.set_progressive(self.progressive);
+        if let ImageSize::Print { dpi, .. } = self.size {
+            let dpi = u16::try_from(dpi).unwrap_or(u16::MAX);
+            encoder.set_density(Density::Inch { x: dpi, y: dpi });
+        }
+        // The photo is always written as RGB, so a profile of other colors, e.g. of a CMYK photo
+        // that has been converted while it was decoded, would no longer match
+        if let Some(icc_profile) = icc_profile.filter(|profile| is_rgb_profile(profile)) {
+            encoder
+                .add_icc_profile(&icc_profile)
+                .map_err(io::Error::other)?;
+        }
+        encoder
+            .encode(image.to_rgb8().as_raw(), width, height, ColorType::Rgb)
+            .map_err(io::Error::other)
+    }
+}
+
+/// Whether the given ICC profile describes RGB colors, according to the color space in its
+/// header.
+fn is_rgb_profile(profile: &[u8]) -> bool {
+    profile.get(16..20) == Some(b"RGB ")
+}
+
+/// Read the size of the image at the given path as it is displayed, i.e. with its EXIF
+/// orientation applied.
+pub fn displayed_size(path: &Path) -> io::Result<(u32, u32)> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()
+        .map_err(io::Error::other)?;
+    let (width, height) = decoder.dimensions();
+    match decoder.orientation().map_err(io::Error::other)? {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => Ok((height, width)),
+        _ => Ok((width, height)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start of an ICC profile header with the given color space.
+    fn profile(color_space: &[u8; 4]) -> Vec<u8> {
+        let mut header = vec![0; 128];
+        header[16..20].copy_from_slice(color_space);
+        header
+    }
+
+    #[test]
+    fn only_rgb_profiles_are_kept() {
+        assert!(is_rgb_profile(&profile(b"RGB ")));
+        assert!(!is_rgb_profile(&profile(b"CMYK")));
+        assert!(!is_rgb_profile(&profile(b"GRAY")));
+        assert!(!is_rgb_profile(&[0; 18]));
+    }
+}
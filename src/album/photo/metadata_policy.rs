@@ -0,0 +1,258 @@
+use clap::ValueEnum;
+use itertools::Itertools;
+use rexiv2::{GpsInfo, Metadata, Orientation};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Mean earth radius in meters, used to compute distances between GPS coordinates.
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Copyright and author tags, which are kept by [MetadataPolicy::Copyright].
+const COPYRIGHT_TAGS: [&str; 6] = [
+    "Exif.Image.Copyright",
+    "Exif.Image.Artist",
+    "Iptc.Application2.Copyright",
+    "Iptc.Application2.Byline",
+    "Xmp.dc.rights",
+    "Xmp.dc.creator",
+];
+
+/// Parts of tag names that identify a camera or its owner, which are removed by
+/// [MetadataPolicy::Reduce].
+const PERSONAL_TAG_PARTS: [&str; 3] = ["SerialNumber", "OwnerName", "InternalSerial"];
+
+/// IPTC and XMP location tags that are precise enough to find a place, which are removed
+/// together with the GPS coordinates inside a privacy zone.
+const LOCATION_TAGS: [&str; 2] = ["Iptc.Application2.SubLocation", "Xmp.iptc.Location"];
+
+/// Which metadata of the source photos is kept in the written photos.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MetadataPolicy {
+    /// Keep all metadata
+    #[default]
+    Keep,
+    /// Keep all metadata, but round the GPS coordinates and remove camera serial numbers and
+    /// owner names
+    Reduce,
+    /// Keep only the copyright notice and the author
+    Copyright,
+    /// Remove all metadata
+    Strip,
+}
+
+/// A circular area around a GPS position, inside which the location of photos is removed,
+/// e.g. around the home of the photographer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrivacyZone {
+    latitude: f64,
+    longitude: f64,
+    radius_m: f64,
+}
+
+impl PrivacyZone {
+    /// Check whether the given position is inside this zone, using the haversine distance.
+    fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        let distance = 2.0 * EARTH_RADIUS_M * a.sqrt().asin();
+        distance <= self.radius_m
+    }
+}
+
+impl FromStr for PrivacyZone {
+    type Err = String;
+
+    /// Parse a privacy zone in the format `LATITUDE,LONGITUDE,RADIUS_IN_METERS`,
+    /// e.g. `48.137,11.575,500`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid number in privacy zone: {}", e))?;
+        match parts[..] {
+            [latitude, _, _] if !(-90.0..=90.0).contains(&latitude) => Err(format!(
+                "The latitude of a privacy zone must be between -90 and 90 degrees, not {}",
+                latitude
+            )),
+            [_, longitude, _] if !(-180.0..=180.0).contains(&longitude) => Err(format!(
+                "The longitude of a privacy zone must be between -180 and 180 degrees, not {}",
+                longitude
+            )),
+            [_, _, radius_m] if !(radius_m.is_finite() && radius_m >= 0.0) => Err(format!(
+                "The radius of a privacy zone must be a non-negative number of meters, not {}",
+                radius_m
+            )),
+            [latitude, longitude, radius_m] => Ok(Self {
+                latitude,
+                longitude,
+                radius_m,
+            }),
+            _ => Err("A privacy zone must be given as LATITUDE,LONGITUDE,RADIUS".to_string()),
+        }
+    }
+}
+
+/// Decides which metadata of a source photo ends up in the written photo.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrivacySettings {
+    pub policy: MetadataPolicy,
+    /// Number of decimal places GPS coordinates are rounded to by [MetadataPolicy::Reduce].
+    /// Two decimal places are about one kilometer.
+    pub gps_precision: u8,
+    /// The location is removed from all photos taken inside one of these zones.
+    pub zones: Vec<PrivacyZone>,
+}
+
+impl PrivacySettings {
+    /// A short description of these settings.
+    /// Two photos with the same content and the same variant result in the same output file.
+    pub fn variant(&self) -> String {
+        let policy = match self.policy {
+            MetadataPolicy::Keep => "keep",
+            MetadataPolicy::Reduce => "reduce",
+            MetadataPolicy::Copyright => "copyright",
+            MetadataPolicy::Strip => "strip",
+        };
+        let zones = self
+            .zones
+            .iter()
+            .map(|zone| format!("{},{},{}", zone.latitude, zone.longitude, zone.radius_m))
+            .join(";");
+        format!("m{}g{}z{}", policy, self.gps_precision, zones)
+    }
+
+    fn clear_tags_where(metadata: &Metadata, matches: impl Fn(&str) -> bool) {
+        let tags = [
+            metadata.get_exif_tags(),
+            metadata.get_iptc_tags(),
+            metadata.get_xmp_tags(),
+        ];
+        for tag in tags.into_iter().flatten().flatten() {
+            if matches(&tag) {
+                metadata.clear_tag(&tag);
+            }
+        }
+    }
+
+    fn remove_location(metadata: &Metadata) {
+        metadata.delete_gps_info();
+        Self::clear_tags_where(metadata, |tag| {
+            tag.starts_with("Xmp.exif.GPS") || LOCATION_TAGS.iter().any(|t| tag.starts_with(t))
+        })
+    }
+
+    fn round_location(&self, metadata: &Metadata) -> io::Result<()> {
+        if let Some(gps) = metadata.get_gps_info() {
+            let factor = 10f64.powi(self.gps_precision as i32);
+            let rounded = GpsInfo {
+                latitude: (gps.latitude * factor).round() / factor,
+                longitude: (gps.longitude * factor).round() / factor,
+                altitude: gps.altitude.round(),
+            };
+            // Remove all other GPS fields like the exact time and direction
+            Self::remove_location(metadata);
+            metadata.set_gps_info(&rounded).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    fn keep_only_copyright(metadata: &Metadata) -> io::Result<()> {
+        let kept = COPYRIGHT_TAGS
+            .iter()
+            .filter_map(|tag| {
+                metadata
+                    .get_tag_multiple_strings(tag)
+                    .ok()
+                    .filter(|values| !values.is_empty())
+                    .map(|values| (*tag, values))
+            })
+            .collect::<Vec<_>>();
+        metadata.clear();
+        for (tag, values) in kept {
+            let values = values.iter().map(|v| v.as_str()).collect::<Vec<_>>();
+            metadata
+                .set_tag_multiple_strings(tag, &values)
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    /// Write the metadata of the photo at `source` into the written photo at `dest`,
+    /// according to these settings.
+    /// If the photo has been re-encoded, its orientation is reset and the embedded thumbnail is
+    /// removed, because they do not match the new image anymore.
+    pub fn apply(&self, source: &Path, dest: &Path, reencoded: bool) -> io::Result<()> {
+        if !reencoded && self.policy == MetadataPolicy::Keep && self.zones.is_empty() {
+            // The copied file already contains all metadata
+            return Ok(());
+        }
+        let metadata = Metadata::new_from_path(source).map_err(io::Error::other)?;
+        if let Some(gps) = metadata.get_gps_info() {
+            if self
+                .zones
+                .iter()
+                .any(|z| z.contains(gps.latitude, gps.longitude))
+            {
+                Self::remove_location(&metadata);
+            }
+        }
+        match self.policy {
+            MetadataPolicy::Keep => {},
+            MetadataPolicy::Reduce => {
+                self.round_location(&metadata)?;
+                Self::clear_tags_where(&metadata, |tag| {
+                    PERSONAL_TAG_PARTS.iter().any(|part| tag.contains(part))
+                });
+            },
+            MetadataPolicy::Copyright => Self::keep_only_copyright(&metadata)?,
+            MetadataPolicy::Strip => metadata.clear(),
+        }
+        if reencoded {
+            metadata.set_orientation(Orientation::Normal);
+            metadata.clear_tag("Exif.Photo.PixelXDimension");
+            metadata.clear_tag("Exif.Photo.PixelYDimension");
+            metadata.erase_thumbnail();
+        }
+        metadata.save_to_file(dest).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_privacy_zones() {
+        let zone = "48.137, 11.575, 500".parse::<PrivacyZone>().unwrap();
+        assert_eq!(
+            zone,
+            PrivacyZone {
+                latitude: 48.137,
+                longitude: 11.575,
+                radius_m: 500.0,
+            }
+        );
+        assert!("-90,180,0".parse::<PrivacyZone>().is_ok());
+        assert!("48.137,11.575".parse::<PrivacyZone>().is_err());
+        assert!("48.137,east,500".parse::<PrivacyZone>().is_err());
+    }
+
+    #[test]
+    fn reject_positions_outside_the_globe() {
+        for zone in [
+            "90.1,11.575,500",
+            "-91,11.575,500",
+            "NaN,11.575,500",
+            "48.137,180.5,500",
+            "48.137,-inf,500",
+            "48.137,11.575,-1",
+            "48.137,11.575,inf",
+        ] {
+            assert!(zone.parse::<PrivacyZone>().is_err(), "{}", zone);
+        }
+    }
+}
@@ -3,14 +3,31 @@ pub(crate) mod SinglePhoto;
 pub(crate) mod TagMarker;
 pub(crate) mod ThreePhotos;
 pub(crate) mod TwoPhotos;
+pub(crate) mod build_cache;
+pub(crate) mod export_target;
+pub(crate) mod image_processing;
+pub(crate) mod metadata_policy;
+pub(crate) mod output_registry;
+pub(crate) mod watermark;
 
+use crate::album::model::{ContainerModel, PhotoModel};
+use crate::album::pdf::{PrintBlock, PrintLayout};
+use crate::album::photo::export_target::ExportTarget;
+use crate::album::photo::image_processing::{ImageProfile, ImageSize};
+use crate::album::photo::metadata_policy::PrivacySettings;
+use crate::album::photo::output_registry::{OutputRegistry, OutputSlot, PendingWrite};
+use crate::album::photo::watermark::Watermark;
 use crate::album::photo::PhotoLoadingError::{ExifParseError, IOError};
+use crate::album::templates::Templates;
+use crate::album::website;
+use clap::ValueEnum;
+use itertools::Itertools;
 use rexiv2::Rexiv2Error;
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Display, Formatter};
+use std::io;
 use std::io::{ErrorKind, Write};
 use std::path::{Path, PathBuf};
-use std::{fs, io};
 
 pub enum PhotoLoadingError {
     IOError { e: io::Error },
@@ -35,11 +52,29 @@ impl Display for PhotoLoadingError {
         }
     }
 }
+/// How the file names of photos that are written to the output directory are chosen.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum FilenameStyle {
+    /// Transliterate file names into safe ASCII names, e.g. `Straße in Łódź.jpg` becomes
+    /// `Strasse_in_Lodz.jpg`
+    #[default]
+    Slug,
+    /// Keep the original file names of the photos
+    Original,
+}
+
 #[derive(Clone)]
 pub struct Photo {
     heading: String,
     description: String,
     source: PathBuf,
+    /// The IPTC copyright notice of the photo, or its author if there is no notice
+    copyright: Option<String>,
+    /// Displayed width and height of the image at `source`, if known
+    size: Option<(u32, u32)>,
+    /// All generated sizes of this photo together with their widths, including the photo at
+    /// `source` itself. If this is empty, the photo is printed as plain Markdown image.
+    renditions: Vec<(PathBuf, u32)>,
 }
 
 impl Photo {
@@ -54,17 +89,121 @@ impl Photo {
     }
 }
 
+impl Photo {
+    /// Render the caption of this photo as GitHub-flavored Markdown into HTML.
+    /// Every line break inside the caption (Ctrl + Enter in Lightroom) is kept as a hard line
+    /// break. Raw HTML and dangerous link protocols inside the caption are escaped.
+    pub(crate) fn get_rendered_caption(&self) -> String {
+        let caption = self
+            .get_html_escaped_caption()
+            .lines()
+            .map(|line| line.trim_end())
+            .join("  \n");
+        markdown::to_html_with_options(&caption, &markdown::Options::gfm())
+            .unwrap_or_else(|_| markdown::to_html(&caption))
+    }
+    /// The caption of this photo as plain text, without Markdown formatting, for the native PDF
+    /// album. Line breaks are kept and list items are marked with a bullet.
+    pub(crate) fn get_plain_caption(&self) -> String {
+        self.get_html_escaped_caption()
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                let (bullet, text) =
+                    match line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+                        Some(item) => ("• ", item),
+                        None => ("", line),
+                    };
+                let text = markdown::to_mdast(text, &markdown::ParseOptions::gfm())
+                    .map(|node| node.to_string())
+                    .unwrap_or_else(|_| text.to_string());
+                format!("{}{}", bullet, text)
+            })
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+impl Photo {
+    /// A photo of the given source file with the given title and caption, without reading it.
+    pub(crate) fn for_test(source: &str, title: &str, caption: &str) -> Self {
+        Self {
+            heading: title.to_string(),
+            description: caption.to_string(),
+            source: PathBuf::from(source),
+            copyright: None,
+            size: None,
+            renditions: vec![],
+        }
+    }
+}
+
+impl Photo {
+    /// The title and the caption of this photo as blocks below the given photos in the native PDF
+    /// album. Empty titles and captions are left out.
+    pub(crate) fn print_blocks<'a>(&'a self, rows: Vec<Vec<&'a Photo>>) -> Vec<PrintBlock<'a>> {
+        let mut blocks = rows.into_iter().map(PrintBlock::Row).collect::<Vec<_>>();
+        let title = self.get_html_escaped_title();
+        if !title.is_empty() {
+            blocks.push(PrintBlock::Title(title));
+        }
+        let caption = self.get_plain_caption();
+        if !caption.trim().is_empty() {
+            blocks.push(PrintBlock::Caption {
+                text: caption,
+                photo: self,
+            });
+        }
+        blocks
+    }
+    /// The model of this photo for the JSON export.
+    pub(crate) fn model(&self) -> PhotoModel {
+        PhotoModel {
+            title: self.get_html_escaped_title(),
+            caption: self.get_html_escaped_caption(),
+            path: self.source.clone(),
+            copyright: self.copyright.clone(),
+        }
+    }
+    /// This photo with the given title and caption instead of its own, where they are given.
+    pub(crate) fn with_text(mut self, title: Option<&str>, caption: Option<&str>) -> Self {
+        if let Some(title) = title {
+            self.heading = title.to_string();
+        }
+        if let Some(caption) = caption {
+            self.description = caption.to_string();
+        }
+        self
+    }
+    /// The path of the image of this photo.
+    pub(crate) fn get_path(&self) -> &Path {
+        &self.source
+    }
+    /// The displayed width and height of the image of this photo, if known.
+    pub(crate) fn get_size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+    /// The path of this photo, followed by the paths of all its generated sizes.
+    pub(crate) fn get_files(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.source.as_path())
+            .chain(self.renditions.iter().map(|(path, _)| path.as_path()))
+    }
+}
+
 impl Photo {
     pub(crate) fn get_relative_path(&self) -> PathBuf {
-        let foldername = self
-            .source
-            .parent()
-            .map(|p| p.file_name())
-            .unwrap()
-            .unwrap();
-        let filename = self.source.file_name().unwrap();
+        Self::relative_path(&self.source)
+    }
+    fn relative_path(path: &Path) -> PathBuf {
+        let foldername = path.parent().map(|p| p.file_name()).unwrap().unwrap();
+        let filename = path.file_name().unwrap();
         PathBuf::from(foldername).join(filename)
     }
+    fn path_string(path: PathBuf) -> io::Result<String> {
+        path.into_os_string()
+            .into_string()
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid Path in image detected!"))
+    }
 }
 
 impl Photo {
@@ -76,67 +215,191 @@ impl Photo {
         let description = exif
             .get_tag_string("Iptc.Application2.Caption")
             .unwrap_or("".to_string());
+        let copyright = exif
+            .get_tag_string("Iptc.Application2.Copyright")
+            .ok()
+            .or_else(|| {
+                exif.get_tag_string("Iptc.Application2.Byline")
+                    .ok()
+                    .map(|byline| format!("© {}", byline.trim()))
+            })
+            .filter(|copyright| !copyright.trim().is_empty());
         Ok(Photo {
             heading,
             description,
             source,
+            copyright,
+            size: None,
+            renditions: vec![],
         })
     }
-    fn normalize_filename(&self, filename: &OsStr) -> OsString {
-        //TODO OSString may lose invalid bytes in the process!
-        OsString::from(
-            filename
-                .to_string_lossy()
-                .chars()
-                .map(|c| match c {
-                    ' ' => '_',
-                    'ä' => 'a',
-                    'ö' => 'o',
-                    'ü' => 'u',
-                    'Ä' => 'A',
-                    'Ö' => 'O',
-                    'Ü' => 'U',
-                    'ß' => 's',
-                    'é' => 'e',
-                    'É' => 'E',
-                    '!' => '_',
-                    'ł' => 'l',
-                    x => x,
-                })
-                .collect::<String>(),
-        )
+    fn normalize_filename(&self, filename: &OsStr, style: FilenameStyle) -> OsString {
+        match style {
+            FilenameStyle::Original => filename.to_os_string(),
+            FilenameStyle::Slug => {
+                let path = Path::new(filename);
+                let mut slug = website::slug_with(path.file_stem().unwrap_or(filename), '_');
+                if slug.is_empty() {
+                    slug.push_str("photo");
+                }
+                if let Some(ext) = path.extension() {
+                    slug.push('.');
+                    slug.push_str(&website::slug_with(ext, '_'));
+                }
+                OsString::from(slug)
+            },
+        }
+    }
+
+    /// Reserve a file with the given name in `out_dir` for the source file of this photo and
+    /// queue it to be written, processed with the given image profile, or copied if there is
+    /// none.
+    /// The watermark is drawn onto the photo if it is processed.
+    /// The metadata of the written file is then reduced according to the privacy settings.
+    /// Returns the path of the file, or the path of an identical file written before.
+    fn write_file(
+        &self,
+        out_dir: &Path,
+        filename: &OsStr,
+        image_profile: Option<&ImageProfile>,
+        privacy: &PrivacySettings,
+        watermark: Option<&Watermark>,
+        registry: &mut OutputRegistry,
+        slot_width: f32,
+    ) -> io::Result<PathBuf> {
+        let watermark = watermark.filter(|_| image_profile.is_some());
+        let mut variant = match image_profile {
+            Some(profile) => profile.variant(slot_width),
+            None => "original".to_string(),
+        };
+        variant.push_str(&privacy.variant());
+        if let Some(watermark) = watermark {
+            variant.push_str(&watermark.variant());
+        }
+        match registry.reserve(&self.source, out_dir, filename, &variant)? {
+            OutputSlot::New(out_path) => {
+                registry.enqueue(PendingWrite {
+                    source: self.source.clone(),
+                    out_path: out_path.clone(),
+                    image_profile: image_profile.cloned(),
+                    privacy: privacy.clone(),
+                    watermark: watermark.cloned(),
+                    copyright: self.copyright.clone(),
+                    slot_width,
+                });
+                Ok(out_path)
+            },
+            OutputSlot::Existing(out_path) => Ok(out_path),
+        }
     }
 
-    /// Write this photo into an "img" subfolder of the given folder.
+    /// Plan writing this photo into the image subfolder of the given export target inside the
+    /// given folder.
+    /// If the export target has an image profile, the photo is resized and re-encoded for a slot
+    /// with the given width, as fraction of the page width. Otherwise, the original file is
+    /// copied.
+    /// If the export target has srcset widths, smaller copies of the photo are written for all
+    /// widths below the width of the processed photo.
     /// If there already exists a photo with the same name, this will change the output name of
-    /// the photo to a unique name.
-    /// Special characters in the source images will be truncated to underscores in the target image.
-    pub fn write_to_directory(&self, target: &Path) -> io::Result<Self> {
+    /// the photo to a unique name by appending a numeric suffix.
+    /// If a photo with identical content has already been written the same way during this album
+    /// run, the photo is not written again and the existing file is used instead.
+    /// The metadata of the written photos is reduced according to the privacy settings of the
+    /// export target.
+    /// Depending on the filename style of the registry, the file name is either kept or
+    /// transliterated into a safe ASCII file name.
+    /// The files are only queued in the registry, and are written by
+    /// [OutputRegistry::write_pending].
+    pub fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+        slot_width: f32,
+    ) -> io::Result<Self> {
         let filename = self.source.file_name().ok_or(io::Error::new(
             ErrorKind::Unsupported,
             "Directories are not supported as photos!",
         ))?;
-        let out_path = target.join("img").join(self.normalize_filename(filename));
-        fs::create_dir_all(out_path.parent().unwrap())?;
-        fs::copy(&self.source, &out_path)?;
+        let out_dir = target.join(export.image_dir);
+        let filename = self.normalize_filename(filename, registry.filename_style());
+        let image_profile = export.image_profile.as_ref();
+        let out_path = self.write_file(
+            &out_dir,
+            &filename,
+            image_profile,
+            &export.privacy,
+            export.watermark.as_ref(),
+            registry,
+            slot_width,
+        )?;
+        let size = match image_profile {
+            Some(profile) => profile.output_size(&self.source, slot_width)?,
+            None => image_processing::displayed_size(&self.source)?,
+        };
+        let mut renditions = vec![];
+        if let Some(image_profile) = image_profile.filter(|_| !export.srcset_widths.is_empty()) {
+            for &width in export.srcset_widths.iter().filter(|w| **w < size.0) {
+                let profile = image_profile.with_size(ImageSize::Width(width));
+                let rendition = self.write_file(
+                    &out_dir,
+                    &OutputRegistry::with_stem_suffix(&filename, &format!("_{}w", width)),
+                    Some(&profile),
+                    &export.privacy,
+                    export.watermark.as_ref(),
+                    registry,
+                    slot_width,
+                )?;
+                renditions.push((rendition, width));
+            }
+            renditions.push((out_path.clone(), size.0));
+        }
         Ok(Photo {
             heading: self.heading.clone(),
             description: self.description.clone(),
             source: out_path,
+            copyright: self.copyright.clone(),
+            size: Some(size),
+            renditions,
         })
     }
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> std::io::Result<()> {
-        writeln!(
+
+    /// Write this photo as image into the Markdown file.
+    /// If there are several generated sizes of this photo, the image is written as HTML image
+    /// with a srcset, which lets the browser pick the best size for the given `sizes` hint.
+    /// The image is then loaded lazily and its size is given to avoid layout jumps.
+    pub(crate) fn print_markdown(&self, f: &mut dyn Write, sizes: &str) -> std::io::Result<()> {
+        let path = Self::path_string(self.get_relative_path())?;
+        if self.renditions.is_empty() {
+            writeln!(f, "![Missing Image: {0}]({0})", path)?;
+            return Ok(());
+        }
+        let srcset = self
+            .renditions
+            .iter()
+            .map(|(rendition, width)| {
+                Self::path_string(Self::relative_path(rendition)).map(|rendition| {
+                    // Spaces and commas would split the srcset candidate
+                    format!(
+                        "{} {}w",
+                        rendition.replace(' ', "%20").replace(',', "%2C"),
+                        width
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?
+            .join(", ");
+        write!(
             f,
-            "![Missing Image: {0}]({0})",
-            self.get_relative_path()
-                .into_os_string()
-                .into_string()
-                .map_err(|_| io::Error::new(
-                    ErrorKind::InvalidData,
-                    "Invalid Path in image detected!"
-                ))?
+            "<img src=\"{0}\" srcset=\"{1}\" sizes=\"{2}\" alt=\"Missing Image: {0}\" loading=\"lazy\"",
+            website::escape(&path),
+            website::escape(&srcset),
+            sizes
         )?;
+        if let Some((width, height)) = self.size {
+            write!(f, " width=\"{}\" height=\"{}\"", width, height)?;
+        }
+        writeln!(f, ">")?;
         Ok(())
     }
 }
@@ -151,15 +414,93 @@ impl Display for Photo {
     }
 }
 
+/// The summary of a day of travel, shown on the card of the day in the website index.
+pub struct DaySummary<'a> {
+    pub title: &'a str,
+    /// The unique anchor of the day header inside the album
+    pub anchor: &'a str,
+    pub from: Option<&'a str>,
+    pub to: Option<&'a str>,
+    /// Distance in kilometers
+    pub distance: Option<&'a str>,
+    pub photo: Option<&'a Photo>,
+}
+
+impl DaySummary<'_> {
+    /// The start and the destination of the day, separated by an arrow, if any is known.
+    pub fn route(&self) -> Option<String> {
+        Some([self.from, self.to].into_iter().flatten().join(" → ")).filter(|r| !r.is_empty())
+    }
+}
+
 pub trait PhotoContainer {
-    /// Write everything out as Markdown file.
+    /// Write everything out as Markdown file, rendered with the template of this container.
+    /// The index is the position of this container inside its document.
     /// The generated markdown file contains the paths stored inside this object.
     /// If photos need to be copied before generating the markdown file,
     /// call write_to_directory before calling this function!
-    fn print_markdown(&self, f: &mut Box<dyn Write>) -> io::Result<()>;
+    fn print_markdown(
+        &self,
+        templates: &Templates,
+        index: usize,
+        f: &mut dyn Write,
+    ) -> io::Result<()>;
     /// Write all photos to the given directory.
     /// Returns a copy of itself if successful, which contains the updated paths.
     /// All print calls must be done on the copy in order to make sure the paths match.
-    fn write_to_directory(&self, target: &Path) -> io::Result<Box<dyn PhotoContainer>>;
+    /// The photos are processed for the given export target.
+    /// The given registry must be shared by all photos written during the same album run.
+    /// The photos are only queued in the registry, and are written by
+    /// [OutputRegistry::write_pending].
+    fn write_to_directory(
+        &self,
+        target: &Path,
+        export: &ExportTarget,
+        registry: &mut OutputRegistry,
+    ) -> io::Result<Box<dyn PhotoContainer>>;
     fn photos(&self) -> Box<dyn Iterator<Item = &Photo> + '_>;
+    /// The blocks of this container, in the order they are laid out in the native PDF album,
+    /// and whether they may be split onto several pages.
+    fn print_layout(&self) -> PrintLayout<'_>;
+    /// Write this container as call of its function of the Typst template.
+    fn print_typst(&self, f: &mut dyn Write) -> io::Result<()>;
+    /// The model of this container and its photos for the JSON export.
+    fn model(&self) -> ContainerModel;
+    /// The summary of the day this container starts, or none if it does not start a day.
+    fn day_summary(&self) -> Option<DaySummary<'_>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slug(name: &OsStr) -> OsString {
+        Photo::for_test("input/photo.jpg", "", "").normalize_filename(name, FilenameStyle::Slug)
+    }
+
+    #[test]
+    fn slug_file_names() {
+        assert_eq!(
+            slug(OsStr::new("Straße in Łódź.JPG")),
+            "Strasse_in_Lodz.JPG"
+        );
+        assert_eq!(slug(OsStr::new("  a - b!?  .jpg")), "a_-_b.jpg");
+        assert_eq!(slug(OsStr::new("a\u{7}b.jpg")), "ab.jpg");
+        assert_eq!(slug(OsStr::new("?!.jpg")), "photo.jpg");
+        let photo = Photo::for_test("input/photo.jpg", "", "");
+        assert_eq!(
+            photo.normalize_filename(OsStr::new("Łódź 1.jpg"), FilenameStyle::Original),
+            "Łódź 1.jpg"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slug_file_names_that_are_not_unicode() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(slug(OsStr::from_bytes(b"IMG\xff\xfe01.jpg")), "IMG_01.jpg");
+        assert_eq!(slug(OsStr::from_bytes(b"\xff.jpg")), "photo.jpg");
+    }
 }
@@ -0,0 +1,324 @@
+use crate::album::photo::build_cache::BuildCache;
+use crate::album::photo::image_processing::ImageProfile;
+use crate::album::photo::metadata_policy::PrivacySettings;
+use crate::album::photo::watermark::Watermark;
+use crate::album::photo::FilenameStyle;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::{fs, io, mem};
+
+/// Keeps track of all photo files that have been written into an output directory during one
+/// album run.
+/// This is used to give every written photo a unique file name and to copy photos with identical
+/// content only once.
+pub struct OutputRegistry {
+    /// How the file names of written photos are chosen.
+    filename_style: FilenameStyle,
+    /// All output paths that are already taken, in lower case to be safe on case-insensitive
+    /// file systems.
+    used_names: HashSet<OsString>,
+    /// Output paths of all written photos by the SHA-256 hash of their source content and the
+    /// processing variant they were written with.
+    written_hashes: HashMap<([u8; 32], String), PathBuf>,
+    /// Cached hashes and written photos from earlier runs
+    cache: BuildCache,
+    /// Photos that have been assigned an output path, but have not been written yet
+    pending: Vec<PendingWrite>,
+    /// Output paths of photos that have been written to their temporary file, but have not been
+    /// moved to their output path yet
+    written: Vec<PathBuf>,
+}
+
+/// The location a photo should be written to, as decided by the [OutputRegistry].
+pub enum OutputSlot {
+    /// The photo has not been written yet and needs to be copied to the given path.
+    New(PathBuf),
+    /// A photo with identical content has already been written to the given path, either during
+    /// this run or during an earlier run.
+    Existing(PathBuf),
+}
+
+/// A photo that still needs to be written to its reserved output path.
+pub struct PendingWrite {
+    pub source: PathBuf,
+    pub out_path: PathBuf,
+    /// How the photo is resized and re-encoded. If this is none, the original file is copied.
+    pub image_profile: Option<ImageProfile>,
+    pub privacy: PrivacySettings,
+    pub watermark: Option<Watermark>,
+    /// Copyright notice of the photo, used for text watermarks without explicit text
+    pub copyright: Option<String>,
+    /// Width of the slot the photo is placed in, as fraction of the page width
+    pub slot_width: f32,
+}
+
+impl PendingWrite {
+    /// The temporary file the photo is written to, next to its output path.
+    fn temp_path(out_path: &Path) -> PathBuf {
+        let mut name = out_path.as_os_str().to_os_string();
+        name.push(".new");
+        PathBuf::from(name)
+    }
+
+    /// Write the photo into its temporary file, processed with its image profile or copied if
+    /// there is none, and reduce its metadata according to the privacy settings.
+    fn write(&self) -> io::Result<()> {
+        if let Some(parent) = self.out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = Self::temp_path(&self.out_path);
+        match &self.image_profile {
+            Some(profile) => profile.process(
+                &self.source,
+                &temp_path,
+                self.slot_width,
+                self.watermark.as_ref(),
+                self.copyright.as_deref(),
+            )?,
+            None => {
+                fs::copy(&self.source, &temp_path)?;
+            },
+        }
+        self.privacy
+            .apply(&self.source, &temp_path, self.image_profile.is_some())
+    }
+}
+
+impl OutputRegistry {
+    pub fn new(filename_style: FilenameStyle, cache: BuildCache) -> Self {
+        Self {
+            filename_style,
+            used_names: HashSet::new(),
+            written_hashes: HashMap::new(),
+            cache,
+            pending: vec![],
+            written: vec![],
+        }
+    }
+
+    pub fn filename_style(&self) -> FilenameStyle {
+        self.filename_style
+    }
+
+    fn name_key(path: &Path) -> OsString {
+        path.as_os_str().to_ascii_lowercase()
+    }
+
+    /// Append a suffix to the file stem of the given file name, e.g. `IMG_0001_480w.jpg`.
+    pub fn with_stem_suffix(filename: &OsStr, suffix: &str) -> OsString {
+        let path = Path::new(filename);
+        let mut name = path.file_stem().unwrap_or(filename).to_os_string();
+        name.push(suffix);
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        name
+    }
+
+    /// Build the candidate file name with the given numeric suffix, e.g. `IMG_0001_2.jpg`.
+    fn with_suffix(filename: &OsStr, suffix: usize) -> OsString {
+        if suffix == 0 {
+            return filename.to_os_string();
+        }
+        Self::with_stem_suffix(filename, &format!("_{}", suffix))
+    }
+
+    /// Reserve an output path inside `directory` for the photo at `source`, which should be
+    /// written with the (already normalized) file name `filename`.
+    /// `variant` describes how the photo is processed while it is written, e.g. its target size.
+    /// If a photo with the same content has already been written with the same variant during
+    /// this run or during the previous run, its path is returned instead.
+    /// If the file name is already taken by another photo, a numeric suffix is appended to the
+    /// file name until it is unique.
+    pub fn reserve(
+        &mut self,
+        source: &Path,
+        directory: &Path,
+        filename: &OsStr,
+        variant: &str,
+    ) -> io::Result<OutputSlot> {
+        let hash = self.cache.source_hash(source)?;
+        let key = (hash, variant.to_string());
+        if let Some(existing) = self.written_hashes.get(&key) {
+            return Ok(OutputSlot::Existing(existing.clone()));
+        }
+        // Reuse the photo written by the previous run, if it is still unchanged
+        if let Some(previous) = self.cache.previous_artifact(&hash, variant) {
+            if previous.parent() == Some(directory)
+                && self.used_names.insert(Self::name_key(&previous))
+            {
+                self.cache.record_artifact(&hash, variant, &previous);
+                self.written_hashes.insert(key, previous.clone());
+                return Ok(OutputSlot::Existing(previous));
+            }
+        }
+        let mut suffix = 0;
+        let out_path = loop {
+            let candidate = directory.join(Self::with_suffix(filename, suffix));
+            if self.used_names.insert(Self::name_key(&candidate)) {
+                break candidate;
+            }
+            suffix += 1;
+        };
+        self.cache.record_artifact(&hash, variant, &out_path);
+        self.written_hashes.insert(key, out_path.clone());
+        Ok(OutputSlot::New(out_path))
+    }
+
+    /// Compute the content hashes of all given source photos in parallel, before their output
+    /// paths are reserved one after another.
+    pub fn hash_sources(&mut self, sources: &[&Path]) -> io::Result<()> {
+        self.cache.hash_sources(sources)
+    }
+
+    /// Queue the given photo to be written by [Self::write_pending].
+    pub fn enqueue(&mut self, write: PendingWrite) {
+        self.pending.push(write);
+    }
+
+    /// Write all queued photos into temporary files, spread across the worker threads.
+    /// The photos only replace the files at their output paths with [Self::commit_written], so
+    /// that an interrupted or failed build never leaves a partly written photo at its output
+    /// path, and a strict build can keep the previous photos.
+    /// Photos that could not be written are forgotten, and their output paths are returned
+    /// together with the error.
+    pub fn write_pending(&mut self) -> Vec<(PathBuf, io::Error)> {
+        let (written, failed): (Vec<_>, Vec<_>) = mem::take(&mut self.pending)
+            .into_par_iter()
+            .map(|write| match write.write() {
+                Ok(()) => Ok(write.out_path),
+                Err(e) => Err((write.out_path, e)),
+            })
+            .partition(Result::is_ok);
+        self.written
+            .extend(written.into_iter().filter_map(Result::ok));
+        let failed = failed
+            .into_iter()
+            .filter_map(Result::err)
+            .collect::<Vec<_>>();
+        for (out_path, _) in &failed {
+            let _ = fs::remove_file(PendingWrite::temp_path(out_path));
+            self.discard(out_path);
+        }
+        failed
+    }
+
+    /// Move all written photos from their temporary files to their output paths.
+    pub fn commit_written(&mut self) -> io::Result<()> {
+        for out_path in mem::take(&mut self.written) {
+            fs::rename(PendingWrite::temp_path(&out_path), &out_path)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the temporary files of all written photos, keeping the files at their output paths
+    /// untouched.
+    pub fn discard_written(&mut self) {
+        for out_path in mem::take(&mut self.written) {
+            let _ = fs::remove_file(PendingWrite::temp_path(&out_path));
+        }
+    }
+
+    /// Forget the given reserved output path, because writing the photo failed.
+    fn discard(&mut self, out_path: &Path) {
+        self.cache.discard_artifact(out_path);
+        self.written_hashes.retain(|_, path| path != out_path);
+    }
+
+    /// Remember the given document written during this run, see [BuildCache::record_document].
+    pub fn record_document(&mut self, path: &Path) {
+        self.cache.record_document(path);
+    }
+
+    /// Save the build cache after all photos have been written.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.cache.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A new temporary directory for the given test.
+    fn temp_dir(test: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("radalbum-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn new_path(slot: OutputSlot) -> PathBuf {
+        match slot {
+            OutputSlot::New(path) => path,
+            OutputSlot::Existing(path) => panic!("{} was already written", path.display()),
+        }
+    }
+
+    #[test]
+    fn taken_names_get_a_suffix() {
+        let directory = temp_dir("registry-names");
+        let (first, second, third) = (
+            directory.join("first.jpg"),
+            directory.join("second.jpg"),
+            directory.join("third.jpg"),
+        );
+        fs::write(&first, "first").unwrap();
+        fs::write(&second, "second").unwrap();
+        fs::write(&third, "third").unwrap();
+        let out = directory.join("out");
+        let mut registry = OutputRegistry::new(FilenameStyle::Original, BuildCache::default());
+        let reserve = |registry: &mut OutputRegistry, source: &Path, name: &str| {
+            new_path(
+                registry
+                    .reserve(source, &out, OsStr::new(name), "")
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            reserve(&mut registry, &first, "IMG_0001.jpg"),
+            out.join("IMG_0001.jpg")
+        );
+        assert_eq!(
+            reserve(&mut registry, &second, "IMG_0001.jpg"),
+            out.join("IMG_0001_1.jpg")
+        );
+        // Names differing only in case would overwrite each other on some file systems
+        assert_eq!(
+            reserve(&mut registry, &third, "img_0001.JPG"),
+            out.join("img_0001_2.JPG")
+        );
+    }
+
+    #[test]
+    fn identical_content_is_written_once() {
+        let directory = temp_dir("registry-dedup");
+        let (first, copy) = (directory.join("a.jpg"), directory.join("b.jpg"));
+        fs::write(&first, "same").unwrap();
+        fs::write(&copy, "same").unwrap();
+        let out = directory.join("out");
+        let mut registry = OutputRegistry::new(FilenameStyle::Original, BuildCache::default());
+        let written = new_path(
+            registry
+                .reserve(&first, &out, OsStr::new("a.jpg"), "")
+                .unwrap(),
+        );
+        match registry
+            .reserve(&copy, &out, OsStr::new("b.jpg"), "")
+            .unwrap()
+        {
+            OutputSlot::Existing(path) => assert_eq!(path, written),
+            OutputSlot::New(path) => panic!("{} was written twice", path.display()),
+        }
+        // Another variant of the same content is written into its own file
+        let resized = registry
+            .reserve(&copy, &out, OsStr::new("a.jpg"), "l480")
+            .unwrap();
+        assert_eq!(new_path(resized), out.join("a_1.jpg"));
+    }
+}
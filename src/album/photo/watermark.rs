@@ -0,0 +1,209 @@
+use crate::album::assets::AssetFile;
+use ab_glyph::{point, Font, FontVec, Glyph, PxScale, ScaleFont};
+use clap::ValueEnum;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Font size in pixels the text is measured at before it is scaled to its final size.
+const MEASURE_SIZE: f32 = 100.0;
+
+/// Where the watermark is placed on each photo.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+/// What is drawn as watermark.
+#[derive(Clone)]
+enum Mark {
+    /// The given text, or the copyright notice of each photo if there is no text
+    Text {
+        text: Option<String>,
+        font: Arc<FontVec>,
+    },
+    /// An image, e.g. a transparent PNG logo
+    Image {
+        source: PathBuf,
+        image: Arc<RgbaImage>,
+    },
+}
+
+/// A visible copyright mark that is drawn onto the photos of an export target.
+#[derive(Clone)]
+pub struct Watermark {
+    mark: Mark,
+    position: WatermarkPosition,
+    /// Opacity of the watermark, from 0 (invisible) to 1 (opaque)
+    opacity: f32,
+    /// Width of the watermark, as fraction of the width of the photo
+    scale: f32,
+}
+
+impl Watermark {
+    /// Create a text watermark.
+    /// If `text` is none, the copyright notice or author of each photo is used.
+    /// The text is drawn with the given font file.
+    pub fn text(
+        text: Option<String>,
+        font: AssetFile,
+        position: WatermarkPosition,
+        opacity: f32,
+        scale: f32,
+    ) -> io::Result<Self> {
+        let font = FontVec::try_from_vec(font.content.into_owned()).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", font.name, e))
+        })?;
+        Ok(Self {
+            mark: Mark::Text {
+                text,
+                font: Arc::new(font),
+            },
+            position,
+            opacity,
+            scale,
+        })
+    }
+
+    /// Create an image watermark from the image file at the given path.
+    pub fn image(
+        source: &Path,
+        position: WatermarkPosition,
+        opacity: f32,
+        scale: f32,
+    ) -> io::Result<Self> {
+        let image = image::open(source).map_err(io::Error::other)?.to_rgba8();
+        Ok(Self {
+            mark: Mark::Image {
+                source: source.to_path_buf(),
+                image: Arc::new(image),
+            },
+            position,
+            opacity,
+            scale,
+        })
+    }
+
+    /// A short description of this watermark.
+    /// Two photos with the same content and the same variant result in the same output file.
+    pub fn variant(&self) -> String {
+        let mark = match &self.mark {
+            Mark::Text { text, .. } => format!("{:?}", text),
+            Mark::Image { source, .. } => format!("{:?}", source),
+        };
+        format!(
+            "{}{:?}o{}s{}",
+            mark, self.position, self.opacity, self.scale
+        )
+    }
+
+    /// Composite the color `source` with the given opacity over the pixel `target`.
+    fn over(source: Rgba<u8>, target: Rgba<u8>, opacity: f32) -> Rgba<u8> {
+        let alpha = source[3] as f32 / 255.0 * opacity.clamp(0.0, 1.0);
+        let target_alpha = target[3] as f32 / 255.0;
+        let out_alpha = alpha + target_alpha * (1.0 - alpha);
+        if out_alpha <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let mut out = [0u8; 4];
+        for channel in 0..3 {
+            out[channel] = ((source[channel] as f32 * alpha
+                + target[channel] as f32 * target_alpha * (1.0 - alpha))
+                / out_alpha)
+                .round() as u8;
+        }
+        out[3] = (out_alpha * 255.0).round() as u8;
+        Rgba(out)
+    }
+
+    /// Render the given text in white with a dark shadow, scaled to the given width.
+    fn render_text(font: &FontVec, text: &str, width: u32) -> RgbaImage {
+        let measured = font.as_scaled(MEASURE_SIZE);
+        let text_width = text
+            .chars()
+            .map(|c| measured.h_advance(font.glyph_id(c)))
+            .sum::<f32>()
+            .max(1.0);
+        let font = font.as_scaled(PxScale::from(MEASURE_SIZE * width as f32 / text_width));
+        let shadow = (font.height() / 20.0).ceil().max(1.0) as u32;
+        let mut rendered =
+            RgbaImage::new(width + shadow + 1, font.height().ceil() as u32 + shadow + 1);
+        for (offset, color) in [(shadow, [0u8, 0, 0]), (0, [255, 255, 255])] {
+            let mut x = 0.0;
+            for c in text.chars() {
+                let glyph = font.scaled_glyph(c);
+                let advance = font.h_advance(glyph.id);
+                let glyph = Glyph {
+                    position: point(x, font.ascent()),
+                    ..glyph
+                };
+                x += advance;
+                let Some(outlined) = font.outline_glyph(glyph) else {
+                    continue;
+                };
+                let bounds = outlined.px_bounds();
+                outlined.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i64 + gx as i64 + offset as i64;
+                    let py = bounds.min.y as i64 + gy as i64 + offset as i64;
+                    if px < 0 || py < 0 {
+                        return;
+                    }
+                    if let Some(pixel) = rendered.get_pixel_mut_checked(px as u32, py as u32) {
+                        *pixel =
+                            Self::over(Rgba([color[0], color[1], color[2], 255]), *pixel, coverage);
+                    }
+                });
+            }
+        }
+        rendered
+    }
+
+    /// Draw this watermark onto the given image.
+    /// `copyright` is the copyright notice of the photo, which is used for text watermarks
+    /// without explicit text. If there is neither, the image is left unchanged.
+    pub fn apply(&self, image: &mut DynamicImage, copyright: Option<&str>) {
+        let width = ((image.width() as f32 * self.scale).round() as u32).max(1);
+        let mark = match &self.mark {
+            Mark::Text { text, font } => match text.as_deref().or(copyright) {
+                Some(text) if !text.trim().is_empty() => Self::render_text(font, text, width),
+                _ => return,
+            },
+            Mark::Image { image: mark, .. } => {
+                let height = (mark.height() as f32 * width as f32 / mark.width() as f32).round();
+                image::imageops::resize(
+                    mark.as_ref(),
+                    width,
+                    (height as u32).max(1),
+                    image::imageops::FilterType::Lanczos3,
+                )
+            },
+        };
+        let mut canvas = image.to_rgba8();
+        let margin = canvas.width().min(canvas.height()) / 50;
+        let (free_x, free_y) = (
+            canvas.width().saturating_sub(mark.width()),
+            canvas.height().saturating_sub(mark.height()),
+        );
+        let (x, y) = match self.position {
+            WatermarkPosition::TopLeft => (margin, margin),
+            WatermarkPosition::TopRight => (free_x.saturating_sub(margin), margin),
+            WatermarkPosition::BottomLeft => (margin, free_y.saturating_sub(margin)),
+            WatermarkPosition::BottomRight => {
+                (free_x.saturating_sub(margin), free_y.saturating_sub(margin))
+            },
+            WatermarkPosition::Center => (free_x / 2, free_y / 2),
+        };
+        for (mx, my, source) in mark.enumerate_pixels() {
+            let Some(target) = canvas.get_pixel_mut_checked(x + mx, y + my) else {
+                continue;
+            };
+            *target = Self::over(*source, *target, self.opacity);
+        }
+        *image = DynamicImage::ImageRgba8(canvas);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::album::assets::Assets;
+use crate::album::model::{ContainerKind, DayModel};
+use crate::album::photo::Photo;
+use crate::album::website;
+use minijinja::{context, Environment};
+use serde::Serialize;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+/// Directory of the templates among the assets.
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// File names of all templates inside the templates directory.
+/// `caption.md` is included by the templates of the containers, and `album.md` is the shell of
+/// the Markdown album.
+const TEMPLATES: [&str; 7] = [
+    "caption.md",
+    "single-photo.md",
+    "two-photos.md",
+    "three-photos.md",
+    "four-photos.md",
+    "day-header.md",
+    "album.md",
+];
+
+/// The templates the Markdown of the album is rendered with.
+/// Blocks like `{% if %}` do not leave a line break behind, and the `escape` filter escapes text
+/// for HTML and its attributes.
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+/// The context of the template of a container.
+#[derive(Serialize)]
+pub struct ContainerContext {
+    /// Position of the container inside its document, starting at 0
+    pub index: usize,
+    #[serde(rename = "type")]
+    pub kind: ContainerKind,
+    /// Title of the container, which is the title of its last photo
+    pub title: String,
+    /// Caption of the container rendered into HTML, which is the caption of its last photo.
+    /// It has no blank lines, because a blank line would end the raw HTML block in the generated
+    /// Markdown file and cause the rest to be parsed as Markdown again.
+    pub caption: String,
+    pub photos: Vec<PhotoContext>,
+    /// The fields of the day, only set for day headers
+    pub day: Option<DayModel>,
+}
+
+/// A photo inside the context of a container.
+#[derive(Serialize)]
+pub struct PhotoContext {
+    /// The image as HTML, with the sizes offered to the browser
+    pub image: String,
+    pub title: String,
+    /// The caption rendered into HTML without blank lines, see [without_blank_lines]
+    pub caption: String,
+    /// Path of the photo relative to the document
+    pub path: PathBuf,
+}
+
+/// Remove the blank lines from the given HTML, as they would end the raw HTML of a container in
+/// the Markdown file.
+/// Blank lines inside `<pre>` blocks are kept as character reference at the end of the line
+/// before, so that the preformatted text keeps its line breaks.
+fn without_blank_lines(html: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut preformatted = false;
+    for line in html.lines() {
+        if line.trim().is_empty() {
+            match lines.last_mut() {
+                Some(last) if preformatted => {
+                    last.push_str("&#10;");
+                    last.push_str(line);
+                },
+                _ => {},
+            }
+            continue;
+        }
+        if line.contains("<pre") {
+            preformatted = true;
+        }
+        if line.contains("</pre>") {
+            preformatted = false;
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+impl PhotoContext {
+    /// The context of the given photo, shown with the given `sizes` hint for the browser.
+    fn new(photo: &Photo, sizes: &str) -> io::Result<Self> {
+        let mut image: Vec<u8> = vec![];
+        photo.print_markdown(&mut image, sizes)?;
+        Ok(Self {
+            image: String::from_utf8_lossy(&image).trim_end().to_string(),
+            title: photo.get_html_escaped_title(),
+            caption: without_blank_lines(&photo.get_rendered_caption()),
+            path: photo.get_relative_path(),
+        })
+    }
+}
+
+impl ContainerContext {
+    /// The context of a container of the given kind at the given index, with the given photos
+    /// shown with the given `sizes` hint for the browser.
+    pub fn new<'a>(
+        kind: ContainerKind,
+        photos: impl Iterator<Item = &'a Photo>,
+        sizes: &str,
+        index: usize,
+    ) -> io::Result<Self> {
+        let photos = photos
+            .map(|photo| PhotoContext::new(photo, sizes))
+            .collect::<io::Result<Vec<_>>>()?;
+        let (title, caption) = photos
+            .last()
+            .map(|photo| (photo.title.clone(), photo.caption.clone()))
+            .unwrap_or_default();
+        Ok(Self {
+            index,
+            kind,
+            title,
+            caption,
+            photos,
+            day: None,
+        })
+    }
+
+    /// The context with the given fields of the day.
+    pub fn with_day(mut self, day: Option<DayModel>) -> Self {
+        self.day = day;
+        self
+    }
+}
+
+impl Templates {
+    /// Load all templates from the given assets.
+    pub fn load(assets: &Assets) -> io::Result<Self> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_keep_trailing_newline(true);
+        env.add_filter("escape", |text: String| website::escape(&text));
+        for name in TEMPLATES {
+            let template = assets.get(&format!("{}/{}", TEMPLATES_DIR, name))?;
+            let source = String::from_utf8_lossy(&template.content).into_owned();
+            env.add_template_owned(name, source).map_err(|e| {
+                io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid template {}: {}", name, e),
+                )
+            })?;
+        }
+        Ok(Self { env })
+    }
+
+    /// Render the template with the given name and context into the given writer.
+    fn render(&self, name: &str, context: impl Serialize, f: &mut dyn Write) -> io::Result<()> {
+        let rendered = self
+            .env
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|e| io::Error::other(format!("Could not render template {}: {}", name, e)))?;
+        f.write_all(rendered.as_bytes())
+    }
+
+    /// Render the given container with the template of its kind into the given writer.
+    pub fn render_container(
+        &self,
+        context: &ContainerContext,
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let name = match context.kind {
+            ContainerKind::SinglePhoto => "single-photo.md",
+            ContainerKind::TwoPhotos => "two-photos.md",
+            ContainerKind::ThreePhotos => "three-photos.md",
+            ContainerKind::FourPhotos => "four-photos.md",
+            ContainerKind::DayHeader => "day-header.md",
+        };
+        self.render(name, context, f)
+    }
+
+    /// Render the shell of the Markdown album with the given title, table of contents and
+    /// rendered containers into the given writer.
+    pub fn render_album(
+        &self,
+        title: &str,
+        contents: &str,
+        containers: &[String],
+        f: &mut dyn Write,
+    ) -> io::Result<()> {
+        let context = context! { title, contents, containers };
+        self.render("album.md", context, f)
+    }
+}
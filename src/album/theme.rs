@@ -0,0 +1,200 @@
+use crate::album::assets::Assets;
+use crate::album::Album;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::{fs, io};
+
+/// The bundled themes of the HTML album and the website.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// Warm neutrals on white
+    #[default]
+    Classic,
+    /// Plain text on white, without colored blocks
+    Minimal,
+    /// Light text on dark gray, for screens
+    Dark,
+    /// Black serif text without backgrounds, avoiding page breaks inside photo blocks
+    Print,
+}
+
+impl ThemeName {
+    /// File name of the stylesheet of the theme among the assets.
+    pub fn asset(self) -> &'static str {
+        match self {
+            ThemeName::Classic => "theme-classic.css",
+            ThemeName::Minimal => "theme-minimal.css",
+            ThemeName::Dark => "theme-dark.css",
+            ThemeName::Print => "theme-print.css",
+        }
+    }
+}
+
+/// The theme of the album, as set in the `[theme]` table of the album config.
+/// The stylesheet of a theme defines the variables of the album stylesheet, e.g. its palette,
+/// fonts and spacing, inside a `:root` rule, followed by additional rules of the theme.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub name: ThemeName,
+    /// Values replacing the variables of the theme, by their names without the leading `--`
+    pub variables: BTreeMap<String, String>,
+    /// Files used by the theme, e.g. fonts and icons, which are copied into the output directory,
+    /// keeping their path relative to the album config
+    pub files: Vec<PathBuf>,
+    /// Directory the files are relative to
+    #[serde(skip)]
+    pub base: PathBuf,
+}
+
+impl Theme {
+    /// The theme with the given name instead of the configured one.
+    pub fn with_name(mut self, name: Option<ThemeName>) -> Self {
+        if let Some(name) = name {
+            self.name = name;
+        }
+        self
+    }
+
+    /// The album stylesheet with all variables of the theme filled in, followed by the rules of
+    /// the theme.
+    /// The variables are replaced by their values instead of being left to the browser, as
+    /// wkhtmltopdf does not support CSS variables.
+    pub fn stylesheet(&self, assets: &Assets) -> io::Result<Vec<u8>> {
+        let base = assets.get(Album::STYLESHEET)?;
+        let theme = assets.get(self.name.asset())?;
+        let (mut variables, rules) = split_root(&String::from_utf8_lossy(&theme.content));
+        for (name, value) in &self.variables {
+            let name = name.trim_start_matches("--");
+            match variables.iter_mut().find(|(variable, _)| variable == name) {
+                Some((_, old)) => *old = value.clone(),
+                None => eprintln!(
+                    "Unknown variable {} of theme {}, ignoring it!",
+                    name, theme.name
+                ),
+            }
+        }
+        let mut stylesheet = String::from(":root {\n");
+        for (name, value) in &variables {
+            stylesheet.push_str(&format!("    --{}: {};\n", name, value));
+        }
+        stylesheet.push_str("}\n\n");
+        stylesheet.push_str(&String::from_utf8_lossy(&base.content));
+        stylesheet.push('\n');
+        stylesheet.push_str(&rules);
+        for (name, value) in &variables {
+            stylesheet = stylesheet.replace(&format!("var(--{})", name), value);
+        }
+        Ok(stylesheet.into_bytes())
+    }
+
+    /// Copy the files of the theme into the given output directory.
+    /// The files are only rewritten if their content changed.
+    pub fn write_files(&self, path: &Path) -> io::Result<()> {
+        for file in &self.files {
+            // Files outside of the directory of the album config are copied by their name only
+            let inside = file.is_relative()
+                && file
+                    .components()
+                    .all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+            let target = match file.file_name() {
+                Some(_) if inside => path.join(file),
+                Some(name) => path.join(name),
+                None => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Invalid theme file: {}", file.display()),
+                    ))
+                },
+            };
+            let source = self.base.join(file);
+            let content = fs::read(&source).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Could not read theme file {}: {}", source.display(), e),
+                )
+            })?;
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            Album::write_if_changed(&target, &content)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split the given theme stylesheet into the variables declared by its `:root` rule, in their
+/// order, and all other rules. Other declarations of the `:root` rule stay in a `:root` rule of
+/// their own.
+fn split_root(css: &str) -> (Vec<(String, String)>, String) {
+    let Some(start) = css.find(":root") else {
+        return (vec![], css.to_string());
+    };
+    let Some((open, close)) = css[start..]
+        .find('{')
+        .and_then(|open| Some((open, open + css[start + open..].find('}')?)))
+    else {
+        return (vec![], css.to_string());
+    };
+    let (variables, others): (Vec<_>, Vec<_>) = css[start + open + 1..start + close]
+        .split(';')
+        .map(str::trim)
+        .filter(|declaration| !declaration.is_empty())
+        .partition(|declaration| declaration.starts_with("--"));
+    let variables = variables
+        .into_iter()
+        .filter_map(|declaration| declaration[2..].split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let root = if others.is_empty() {
+        String::new()
+    } else {
+        format!(":root {{ {}; }}", others.join("; "))
+    };
+    let rules = format!(
+        "{}{}{}",
+        css[..start].trim_end(),
+        root,
+        &css[start + close + 1..]
+    );
+    (variables, rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_root_variables() {
+        let css = "/* Theme */\n:root {\n    --accent: #361E20;\n    --font: \"DejaVu Sans\", sans-serif;\n}\n\nh1 { color: red; }\n";
+        let (variables, rules) = split_root(css);
+        assert_eq!(
+            variables,
+            vec![
+                ("accent".to_string(), "#361E20".to_string()),
+                (
+                    "font".to_string(),
+                    "\"DejaVu Sans\", sans-serif".to_string()
+                ),
+            ]
+        );
+        assert_eq!(rules, "/* Theme */\n\nh1 { color: red; }\n");
+    }
+
+    #[test]
+    fn split_root_without_root() {
+        let css = "h1 { color: red; }\n";
+        assert_eq!(split_root(css), (vec![], css.to_string()));
+    }
+
+    #[test]
+    fn split_root_keeps_other_declarations() {
+        let (variables, rules) = split_root(":root{--gap:1em;color:red}body{}");
+        assert_eq!(variables, vec![("gap".to_string(), "1em".to_string())]);
+        assert_eq!(rules, ":root { color:red; }body{}");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::album::assets::AssetFile;
+use crate::album::photo::{image_processing, Photo, PhotoContainer};
+use crate::album::Album;
+use std::io;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+/// File name of the default template among the assets.
+pub const DEFAULT_TEMPLATE: &str = "radalbum.typ";
+
+/// The given text as Typst string literal.
+pub fn string(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('"');
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '"' => literal.push_str("\\\""),
+            '\n' => literal.push_str("\\n"),
+            '\r' | '\t' => literal.push(' '),
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// The given photo as Typst dictionary with its path relative to the album and its size.
+pub fn photo(photo: &Photo) -> io::Result<String> {
+    let (width, height) = match photo.get_size() {
+        Some(size) => size,
+        None => image_processing::displayed_size(photo.get_path())?,
+    };
+    let path = photo.get_relative_path();
+    let path = path.to_str().ok_or(io::Error::new(
+        ErrorKind::InvalidData,
+        "Invalid Path in image detected!",
+    ))?;
+    Ok(format!(
+        "(path: {}, width: {}, height: {})",
+        string(&path.replace('\\', "/")),
+        width.max(1),
+        height.max(1)
+    ))
+}
+
+/// The title and the caption of the given photo as named Typst arguments.
+pub fn text_arguments(photo: &Photo) -> String {
+    format!(
+        "title: {}, caption: {}",
+        string(&photo.get_html_escaped_title()),
+        string(&photo.get_plain_caption())
+    )
+}
+
+/// Write the given containers as Typst document to the given path, which uses the functions of
+/// the given template. The template is written next to the document.
+/// The files are only rewritten if their content changed.
+pub fn write_typst(
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    template: &AssetFile,
+    path: &Path,
+) -> io::Result<()> {
+    Album::write_if_changed(&path.with_file_name(&template.name), &template.content)?;
+    let mut document: Vec<u8> = vec![];
+    writeln!(document, "#import {}: *", string(&template.name))?;
+    writeln!(document, "#show: album.with(title: {})", string(title))?;
+    writeln!(document)?;
+    for container in containers {
+        container.print_typst(&mut document)?;
+    }
+    Album::write_if_changed(path, &document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(string("Tag 3: Łódź"), "\"Tag 3: Łódź\"");
+        assert_eq!(string("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(string("C:\\photos"), "\"C:\\\\photos\"");
+        assert_eq!(string("a\nb\tc\r"), "\"a\\nb c \"");
+        // Markup characters are kept, as strings are not parsed as markup
+        assert_eq!(string("*#[$]"), "\"*#[$]\"");
+    }
+}
@@ -0,0 +1,266 @@
+use crate::album::photo::{DaySummary, PhotoContainer};
+use crate::album::templates::Templates;
+use crate::album::Album;
+use deunicode::deunicode_char;
+use itertools::Itertools;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// File name of the index page of the website.
+const INDEX: &str = "index.html";
+
+/// Displayed width of the photo of a day card, as hint for the browser
+const CARD_SIZES: &str = "(min-width: 40em) 33vw, 100vw";
+
+/// One day of the website, which gets its own page.
+struct Day<'a> {
+    summary: DaySummary<'a>,
+    /// File name of the page of the day
+    file: String,
+    /// The containers of the day, starting with its day header
+    containers: &'a [Box<dyn PhotoContainer>],
+}
+
+/// Escape text for use inside HTML and XML elements and double-quoted attributes.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The given text as URL-safe slug of lowercase ASCII letters and digits, separated by dashes.
+/// Unicode characters are transliterated, so `Tag 3: Łódź` becomes `tag-3-lodz`.
+pub(crate) fn slug(text: &str) -> String {
+    slug_with(OsStr::new(text), '-').to_lowercase()
+}
+
+/// The given text as slug of ASCII letters, digits and dashes, e.g. for file names and URLs.
+/// Unicode characters are transliterated (`Łódź` becomes `Lodz`), control characters other
+/// than whitespace are dropped and all other characters become a single `separator`.
+/// The text is read as raw bytes, so bytes that are not valid Unicode become a separator as
+/// well instead of being lost.
+pub(crate) fn slug_with(text: &OsStr, separator: char) -> String {
+    let mut slug = String::new();
+    let mut push = |c: char| {
+        if c.is_ascii_alphanumeric() || (c == '-' && separator != '-') {
+            slug.push(c);
+        } else if (c.is_whitespace() || !c.is_control()) && !slug.ends_with(separator) {
+            slug.push(separator);
+        }
+    };
+    for chunk in text.as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            deunicode_char(c).unwrap_or("?").chars().for_each(&mut push);
+        }
+        if !chunk.invalid().is_empty() {
+            push('?');
+        }
+    }
+    slug.trim_matches(&[separator, '-'][..]).to_string()
+}
+
+/// The given slug, numbered if it is already taken. The returned slug is marked as taken.
+pub(crate) fn unique_slug(taken: &mut HashSet<String>, slug: &str) -> String {
+    let mut unique = slug.to_string();
+    let mut counter = 2;
+    while !taken.insert(unique.clone()) {
+        unique = format!("{}-{}", slug, counter);
+        counter += 1;
+    }
+    unique
+}
+
+/// Render the given Markdown into HTML.
+/// The containers write their photos and day headers as raw HTML, which is kept.
+fn to_html(markdown: &[u8]) -> String {
+    let markdown = String::from_utf8_lossy(markdown);
+    let options = markdown::Options {
+        parse: markdown::ParseOptions::gfm(),
+        compile: markdown::CompileOptions {
+            allow_dangerous_html: true,
+            ..markdown::CompileOptions::gfm()
+        },
+    };
+    markdown::to_html_with_options(&markdown, &options)
+        .unwrap_or_else(|_| markdown::to_html(&markdown))
+}
+
+/// Render the Markdown written by the given containers with the given templates into HTML.
+fn render(containers: &[Box<dyn PhotoContainer>], templates: &Templates) -> io::Result<String> {
+    let mut markdown: Vec<u8> = vec![];
+    for (index, container) in containers.iter().enumerate() {
+        container.print_markdown(templates, index, &mut markdown)?;
+    }
+    Ok(to_html(&markdown))
+}
+
+/// A complete HTML page with the given title and body, using the stylesheet of the album.
+fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{}</title>
+<link rel="stylesheet" href="{}">
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        escape(title),
+        Album::STYLESHEET,
+        body
+    )
+}
+
+/// The card of the given day, linking to the page of the day.
+fn day_card(day: &Day, out: &mut String) -> io::Result<()> {
+    // Writing into a string cannot fail
+    let _ = writeln!(out, "<a class=\"daycard\" href=\"{}\">", escape(&day.file));
+    if let Some(photo) = day.summary.photo {
+        let mut image: Vec<u8> = vec![];
+        photo.print_markdown(&mut image, CARD_SIZES)?;
+        let _ = writeln!(out, "<div class=\"image\">{}</div>", to_html(&image).trim());
+    }
+    let _ = writeln!(out, "<h2>{}</h2>", escape(day.summary.title));
+    if let Some(route) = day.summary.route() {
+        let _ = writeln!(out, "<div class=\"route\">{}</div>", escape(&route));
+    }
+    if let Some(distance) = day.summary.distance {
+        let _ = writeln!(out, "<span class=\"distance\">{}</span>", escape(distance));
+    }
+    let _ = writeln!(out, "</a>");
+    Ok(())
+}
+
+/// The navigation to the previous and the next day.
+fn pagination(previous: Option<&Day>, next: Option<&Day>) -> String {
+    let link = |day: Option<&Day>, rel: &str, label: fn(String) -> String| match day {
+        Some(day) => format!(
+            "<a class=\"{0}\" rel=\"{0}\" href=\"{1}\">{2}</a>",
+            rel,
+            escape(&day.file),
+            label(escape(day.summary.title))
+        ),
+        None => format!("<span class=\"{}\"></span>", rel),
+    };
+    format!(
+        "<nav class=\"pagination\">\n{}\n{}\n</nav>\n",
+        link(previous, "prev", |title| format!("← {}", title)),
+        link(next, "next", |title| format!("{} →", title))
+    )
+}
+
+/// Write the given containers as website into the given output directory, rendering them with
+/// the given templates.
+/// The index page shows the photos before the first day and a card of every day, and every day
+/// gets its own page with a breadcrumb and the navigation to the previous and the next day.
+/// The page of a day is named after the anchor of its day header, so its link stays the same
+/// between builds.
+/// The files are only rewritten if their content changed.
+/// Returns the paths of the pages of the days, which change with the days of the album.
+pub fn write_website(
+    containers: &[Box<dyn PhotoContainer>],
+    title: &str,
+    templates: &Templates,
+    path: &Path,
+) -> io::Result<Vec<PathBuf>> {
+    let starts = containers
+        .iter()
+        .positions(|container| container.day_summary().is_some())
+        .collect::<Vec<_>>();
+    let intro = &containers[..starts.first().copied().unwrap_or(containers.len())];
+    // The index page and the pandoc album must never be overwritten by a day
+    let mut files = HashSet::from(["index".to_string(), "album".to_string()]);
+    let mut days = vec![];
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(containers.len());
+        let summary = containers[start]
+            .day_summary()
+            .expect("days start with a day header");
+        let name = unique_slug(&mut files, summary.anchor);
+        days.push(Day {
+            summary,
+            file: format!("{}.html", name),
+            containers: &containers[start..end],
+        });
+    }
+
+    let mut index = format!("<h1>{}</h1>\n{}", escape(title), render(intro, templates)?);
+    if !days.is_empty() {
+        index.push_str("<nav class=\"daycards\">\n");
+        for day in &days {
+            day_card(day, &mut index)?;
+        }
+        index.push_str("</nav>\n");
+    }
+    Album::write_if_changed(&path.join(INDEX), page(title, &index).as_bytes())?;
+
+    let mut pages = vec![];
+    for (i, day) in days.iter().enumerate() {
+        let previous = i.checked_sub(1).and_then(|i| days.get(i));
+        let navigation = pagination(previous, days.get(i + 1));
+        let body = format!(
+            "<nav class=\"breadcrumb\"><a href=\"{}\">{}</a> › {}</nav>\n{}{}{}",
+            INDEX,
+            escape(title),
+            escape(day.summary.title),
+            navigation,
+            render(day.containers, templates)?,
+            navigation
+        );
+        let page_title = format!("{} – {}", day.summary.title, title);
+        let page_path = path.join(&day.file);
+        Album::write_if_changed(&page_path, page(&page_title, &body).as_bytes())?;
+        pages.push(page_path);
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_url_safe() {
+        assert_eq!(slug("Tag 3: Łódź"), "tag-3-lodz");
+        assert_eq!(slug("  Über -- den  Berg! "), "uber-den-berg");
+        assert_eq!(slug("?!"), "");
+    }
+
+    #[test]
+    fn slugs_with_another_separator() {
+        let slug = |text: &str| slug_with(OsStr::new(text), '_');
+        assert_eq!(slug("Straße in Łódź"), "Strasse_in_Lodz");
+        assert_eq!(slug("  a - b!?  "), "a_-_b");
+        assert_eq!(slug("a\u{7}b"), "ab");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn slugs_keep_invalid_bytes_apart() {
+        use std::os::unix::ffi::OsStrExt;
+        assert_eq!(
+            slug_with(OsStr::from_bytes(b"IMG\xff\xfe01"), '_'),
+            "IMG_01"
+        );
+        assert_eq!(slug_with(OsStr::from_bytes(b"\xff"), '-'), "");
+    }
+
+    #[test]
+    fn taken_slugs_are_numbered() {
+        let mut taken = HashSet::new();
+        assert_eq!(unique_slug(&mut taken, "tag-1"), "tag-1");
+        assert_eq!(unique_slug(&mut taken, "tag-1"), "tag-1-2");
+        assert_eq!(unique_slug(&mut taken, "tag-1"), "tag-1-3");
+        // A title whose slug looks like a numbered slug is numbered itself
+        assert_eq!(unique_slug(&mut taken, "tag-1-2"), "tag-1-2-2");
+        assert_eq!(unique_slug(&mut taken, "tag-2"), "tag-2");
+    }
+}
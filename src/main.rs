@@ -1,49 +1,523 @@
 mod album;
+mod pipeline;
+mod preview;
+mod watch;
 
-use std::env::set_current_dir;
-use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
-use clap::{Parser};
-use crate::album::Album;
+use std::path::{Path, PathBuf};
+use std::{fs, io, process};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::sync::Arc;
+use crate::album::assets::{AssetFile, Assets};
+use crate::album::config::AlbumConfig;
+use crate::album::pdf::book::PrintProfile;
+use crate::album::pdf::{
+    Margins, PageBreak, PageSettings, PaperSize, PdfBackend, PdfFont, PdfSettings,
+};
+use crate::album::photo::build_cache::BuildCache;
+use crate::album::photo::export_target::{ExportTarget, TargetKind};
+use crate::album::photo::image_processing::ImageProfile;
+use crate::album::photo::metadata_policy::{MetadataPolicy, PrivacySettings, PrivacyZone};
+use crate::album::photo::watermark::{Watermark, WatermarkPosition};
+use crate::album::photo::FilenameStyle;
+use crate::album::theme::ThemeName;
+use crate::album::{latex, typst, Album};
+use crate::pipeline::{BuildStep, OutputFormat, StepFailed};
 
 /// Radalbum - Create a photo album from a set of images automatically using metadata stored inside the image files.
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
 struct RadalbumArgs {
+    #[command(subcommand)]
+    command: Option<RadalbumCommand>,
+
+    #[command(flatten)]
+    build: Option<BuildArgs>,
+}
+
+#[derive(Subcommand)]
+enum RadalbumCommand {
+    /// Builds the album, then watches the input directory and the assets for changes and
+    /// rebuilds the album after every change. Requires an output directory.
+    Watch(BuildArgs),
+    /// Builds the album, serves the output directory on localhost and reloads the album in the
+    /// browser after every rebuild. Requires an output directory.
+    Preview(PreviewArgs),
+}
+
+#[derive(Args)]
+struct PreviewArgs {
+    #[command(flatten)]
+    build: BuildArgs,
+
+    /// Sets the port on localhost the album is served on.
+    #[arg(long, default_value_t = 8000)]
+    port: u16,
+}
+
+/// Everything that is needed to build an album.
+#[derive(Args)]
+struct BuildArgs {
     /// Input Directory, containing the source images that should be used in the generated album.
     /// Source images will always be sorted alphabetically by their file path.
     input_directory: PathBuf,
 
+    /// Builds the album from the given manifest file (TOML), which sets the order, the grouping,
+    /// the titles and the captions of the photos. If the file does not exist, it is written with
+    /// the containers detected from the titles of the photos.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// Sets the directory with assets that replace the default assets compiled into the program,
+    /// file by file, e.g. `Album.css`, `radalbum.typ` or `templates/day-header.md`. Defaults to
+    /// `radalbum/assets` inside the configuration directory of the user.
+    #[arg(long, value_name = "DIR")]
+    assets: Option<PathBuf>,
+
+    /// Reads the settings of the album from the given album config file (TOML), e.g. the theme
+    /// with its variables and files inside its `[theme]` table.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Sets the title of the album, overriding the title of the album config. Defaults to the
+    /// name of the input directory.
+    #[arg(long, value_name = "TITLE")]
+    title: Option<String>,
+
+    /// Sets the theme of the HTML album and the website, overriding the theme of the album config.
+    #[arg(long, value_enum, value_name = "THEME")]
+    theme: Option<ThemeName>,
+
+    /// Ignores the build cache in the output directory and reads and writes all photos again.
+    /// Photos of the previous build that are not used anymore are still deleted.
+    #[arg(long)]
+    force: bool,
+
+    /// Sets the number of photos that are loaded and processed at the same time.
+    /// Defaults to the number of CPU cores.
+    #[arg(short, long, value_name = "JOBS")]
+    jobs: Option<usize>,
+
     /// Sets the output directory that will be used to store the generated photo album.
     /// If the directory does not exist yet, it will be created.
     #[arg(short, long, value_name = "DIRECTORY")]
     out: Option<PathBuf>,
+
+    /// Sets the documents that are built into the output directory, separated by commas.
+    /// `markdown` only writes the Markdown files of all documents without building anything
+    /// from them.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "html,pdf")]
+    formats: Vec<OutputFormat>,
+
+    /// Writes a table of contents of all days after the album title, with links in the HTML
+    /// album and page numbers in the native PDF album.
+    #[arg(long)]
+    toc: bool,
+
+    /// Sets how the file names of the photos copied into the output directory are chosen.
+    #[arg(long, value_enum, default_value_t = FilenameStyle::Slug)]
+    filenames: FilenameStyle,
+
+    /// Copy the original photos into the album instead of resizing and re-encoding them.
+    #[arg(long)]
+    original_images: bool,
+
+    /// Sets the maximum length in pixels of the longer edge of the photos in the HTML album.
+    #[arg(long, value_name = "PIXELS", default_value_t = 2048)]
+    web_long_edge: u32,
+
+    /// Sets the JPEG quality (1-100) of the photos in the HTML album.
+    #[arg(long, value_name = "QUALITY", default_value_t = 82,
+        value_parser = clap::value_parser!(u8).range(1..=100))]
+    web_quality: u8,
+
+    /// Sets the widths in pixels of the smaller copies of each photo that are offered to the
+    /// browser in the HTML album, separated by commas.
+    #[arg(long, value_name = "PIXELS", value_delimiter = ',', default_value = "480,960,1440")]
+    web_srcset_widths: Vec<u32>,
+
+    /// Sets the maximum length in pixels of the longer edge of the photos in the EPUB album.
+    #[arg(long, value_name = "PIXELS", default_value_t = 1600)]
+    ebook_long_edge: u32,
+
+    /// Sets the resolution of the photos in the PDF album, in dots per inch of the printed page.
+    #[arg(long, value_name = "DPI", default_value_t = 300)]
+    print_dpi: u32,
+
+    /// Sets the JPEG quality (1-100) of the photos in the PDF album.
+    #[arg(long, value_name = "QUALITY", default_value_t = 92,
+        value_parser = clap::value_parser!(u8).range(1..=100))]
+    print_quality: u8,
+
+    /// Sets how the PDF album is built. `native` lays out the pages itself, `pandoc` requires
+    /// pandoc and wkhtmltopdf and prints the HTML album onto a single long page.
+    #[arg(long, value_enum, default_value_t = PdfBackend::Native)]
+    pdf_backend: PdfBackend,
+
+    /// Sets the paper size of the native PDF album, either a name like `a4`, `a5` or `letter`,
+    /// a photo book size like `square` or `square-large`, or the width and height in millimeters
+    /// like `210x297`.
+    #[arg(long, value_name = "SIZE", default_value = "a4")]
+    paper: PaperSize,
+
+    /// Prints the native PDF album in landscape orientation.
+    #[arg(long)]
+    landscape: bool,
+
+    /// Sets the page margins of the native PDF album in millimeters, either one value for all
+    /// sides or TOP,RIGHT,BOTTOM,LEFT.
+    #[arg(long, value_name = "MILLIMETERS", default_value = "15")]
+    margin: Margins,
+
+    /// Sets where every day starts in the native PDF album.
+    #[arg(long, value_enum, default_value_t = PageBreak::Page)]
+    day_break: PageBreak,
+
+    /// Sets the bleed around the pages of the native PDF album in millimeters.
+    #[arg(long, value_name = "MILLIMETERS", default_value_t = 0.0)]
+    bleed: f32,
+
+    /// Prints the native PDF album as the given photo book, with its trim size, bleed and safe
+    /// zone. Also writes the cover with the spine width for the number of pages, and warns about
+    /// photos with a too low resolution. Overrides `--paper`, `--landscape` and `--bleed`.
+    #[arg(long, value_enum, value_name = "PROFILE")]
+    print_profile: Option<PrintProfile>,
+
+    /// Draws crop marks around the pages of the native PDF album.
+    #[arg(long)]
+    crop_marks: bool,
+
+    /// Sets the LaTeX document class (.cls file) of the LaTeX album. Defaults to the
+    /// `radalbum.cls` class of the assets.
+    #[arg(long, value_name = "FILE")]
+    latex_class: Option<PathBuf>,
+
+    /// Sets the Typst template of the Typst album. Defaults to the `radalbum.typ` template of
+    /// the assets.
+    #[arg(long, value_name = "FILE")]
+    typst_template: Option<PathBuf>,
+
+    /// Sets the font file (TTF or OTF) of the text in the native PDF album. Defaults to the
+    /// bundled DejaVu Sans.
+    #[arg(long, value_name = "FILE")]
+    pdf_font: Option<PathBuf>,
+
+    /// Sets the font file (TTF or OTF) of the headings and titles in the native PDF album.
+    #[arg(long, value_name = "FILE")]
+    pdf_bold_font: Option<PathBuf>,
+
+    /// Sets which metadata of the source photos is kept in the photos of the album.
+    #[arg(long, value_enum, default_value_t = MetadataPolicy::Keep)]
+    metadata: MetadataPolicy,
+
+    /// Sets the number of decimal places the GPS coordinates are rounded to with
+    /// `--metadata reduce`. Two decimal places are about one kilometer.
+    #[arg(long, value_name = "DECIMALS", default_value_t = 2)]
+    gps_precision: u8,
+
+    /// Removes the location from all photos taken within RADIUS meters around the given
+    /// position, e.g. `--privacy-zone 48.137,11.575,500`. Can be given multiple times.
+    #[arg(long, value_name = "LATITUDE,LONGITUDE,RADIUS")]
+    privacy_zone: Vec<PrivacyZone>,
+
+    /// Draws a text watermark onto the photos. Without a text, the IPTC copyright notice or
+    /// author of each photo is used.
+    #[arg(long, value_name = "TEXT", num_args = 0..=1, default_missing_value = "",
+        conflicts_with = "watermark_image")]
+    watermark_text: Option<String>,
+
+    /// Draws the given image, e.g. a transparent PNG logo, as watermark onto the photos.
+    #[arg(long, value_name = "FILE")]
+    watermark_image: Option<PathBuf>,
+
+    /// Sets the font file (TTF or OTF) for text watermarks.
+    #[arg(long, value_name = "FILE")]
+    watermark_font: Option<PathBuf>,
+
+    /// Sets where the watermark is placed on each photo.
+    #[arg(long, value_enum, default_value_t = WatermarkPosition::BottomRight)]
+    watermark_position: WatermarkPosition,
+
+    /// Sets the opacity of the watermark, from 0 (invisible) to 1 (opaque).
+    #[arg(long, value_name = "OPACITY", default_value_t = 0.6)]
+    watermark_opacity: f32,
+
+    /// Sets the width of the watermark, as fraction of the width of each photo.
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.25)]
+    watermark_scale: f32,
+
+    /// Sets the albums the watermark is drawn on, separated by commas.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "web")]
+    watermark_targets: Vec<TargetKind>,
 }
 
-fn main() {
-    let cli = RadalbumArgs::parse();
+/// Which parts of the album are rebuilt.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum BuildScope {
+    /// Only write the stylesheet and rebuild the HTML and PDF album
+    Assets,
+    /// Import all photos and write the complete album
+    Full,
+}
 
-    let input_directory = cli.input_directory;
-    println!("Value for input directory: {input_directory:?}");
+/// The settings of the native PDF album, or none if the PDF album is built with pandoc.
+fn pdf_settings(args: &BuildArgs, assets: &Assets) -> io::Result<Option<PdfSettings>> {
+    if args.pdf_backend != PdfBackend::Native {
+        if args.print_profile.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Print profiles require the native PDF backend!",
+            ));
+        }
+        return Ok(None);
+    }
+    let load = |path: Option<&Path>, bold| {
+        assets
+            .font(path, bold)
+            .and_then(PdfFont::new)
+            .map(Arc::new)
+            .map_err(|e| {
+                io::Error::new(e.kind(), format!("Could not load the PDF font: {}", e))
+            })
+    };
+    let book = args.print_profile.map(PrintProfile::format);
+    let page = match &book {
+        Some(book) => book.page_settings(args.margin),
+        None => PageSettings::new(args.paper, args.landscape, args.margin, args.bleed),
+    };
+    Ok(Some(PdfSettings {
+        page: page.with_crop_marks(args.crop_marks),
+        regular: load(args.pdf_font.as_deref(), false)?,
+        bold: load(args.pdf_bold_font.as_deref(), true)?,
+        chapter_break: args.day_break,
+        book,
+    }))
+}
 
-    let mut album = Album::import_all_photos(&input_directory).unwrap();
+/// The export targets that are needed for the documents selected with `--formats`.
+fn export_targets(args: &BuildArgs, assets: &Assets) -> io::Result<Vec<ExportTarget>> {
+    let privacy = PrivacySettings {
+        policy: args.metadata,
+        gps_precision: args.gps_precision,
+        zones: args.privacy_zone.clone(),
+    };
+    let pdf = if args.formats.contains(&OutputFormat::Pdf) {
+        pdf_settings(args, assets)?
+    } else {
+        None
+    };
+    let latex_class = if args.formats.contains(&OutputFormat::Latex) {
+        Some(match &args.latex_class {
+            Some(class) => AssetFile::load(class)?,
+            None => assets.get(latex::DEFAULT_CLASS)?,
+        })
+    } else {
+        None
+    };
+    let typst_template = if args.formats.contains(&OutputFormat::Typst) {
+        Some(match &args.typst_template {
+            Some(template) => AssetFile::load(template)?,
+            None => assets.get(typst::DEFAULT_TEMPLATE)?,
+        })
+    } else {
+        None
+    };
+    // Photos are printed across the content of the native pages, or across a whole A4 page
+    let page_width = pdf
+        .as_ref()
+        .map_or(210.0, |pdf| pdf.page.content_width());
+    let website = args.formats.contains(&OutputFormat::Website);
+    let targets = if args.original_images {
+        [
+            ExportTarget::web(None, vec![], privacy.clone()).with_website(website),
+            ExportTarget::print(None, privacy.clone())
+                .with_pdf(pdf)
+                .with_latex_class(latex_class)
+                .with_typst_template(typst_template),
+            ExportTarget::ebook(None, privacy),
+        ]
+    } else {
+        [
+            ExportTarget::web(
+                Some(ImageProfile::web(args.web_long_edge, args.web_quality)),
+                args.web_srcset_widths.clone(),
+                privacy.clone(),
+            )
+            .with_website(website),
+            ExportTarget::print(
+                Some(ImageProfile::print(args.print_dpi, args.print_quality, page_width)),
+                privacy.clone(),
+            )
+            .with_pdf(pdf)
+            .with_latex_class(latex_class)
+            .with_typst_template(typst_template),
+            ExportTarget::ebook(
+                Some(ImageProfile::web(args.ebook_long_edge, args.web_quality)),
+                privacy,
+            ),
+        ]
+    };
+    let watermark = if let Some(image) = &args.watermark_image {
+        Some(Watermark::image(
+            image,
+            args.watermark_position,
+            args.watermark_opacity,
+            args.watermark_scale,
+        ))
+    } else {
+        args.watermark_text.as_ref().map(|text| {
+            Watermark::text(
+                Some(text.clone()).filter(|text| !text.is_empty()),
+                assets.font(args.watermark_font.as_deref(), false)?,
+                args.watermark_position,
+                args.watermark_opacity,
+                args.watermark_scale,
+            )
+        })
+    };
+    let watermark = watermark.transpose().map_err(|e| {
+        io::Error::new(e.kind(), format!("Could not load the watermark: {}", e))
+    })?;
+    if watermark.is_some() && args.original_images {
+        eprintln!("Watermarks are only drawn onto resized photos, ignoring the watermark!");
+    }
+    Ok(targets
+        .into_iter()
+        .filter(|target| {
+            args.formats
+                .iter()
+                .any(|format| format.uses_target(target.kind))
+        })
+        .map(|target| {
+            let watermark = watermark
+                .clone()
+                .filter(|_| args.watermark_targets.contains(&target.kind));
+            target
+                .with_watermark(watermark)
+                .with_table_of_contents(args.toc)
+        })
+        .collect())
+}
 
-    if let Some(config_path) = cli.out {
+/// Build the album with the given arguments.
+/// If `strict` is set, the build fails as soon as a single photo cannot be loaded or written,
+/// and the photos and Markdown files of the previous build are kept.
+fn build(args: &BuildArgs, scope: BuildScope, strict: bool) -> io::Result<()> {
+    let config = match &args.config {
+        Some(path) => AlbumConfig::load(path)?,
+        None => AlbumConfig::default(),
+    };
+    let assets = Assets::new(args.assets.clone()).with_theme(config.theme.with_name(args.theme));
+    if scope == BuildScope::Assets {
+        if let Some(config_path) = &args.out {
+            Album::write_aux_files(config_path, &assets)?;
+            build_documents(args, &export_targets(args, &assets)?, config_path)?;
+        }
+        return Ok(());
+    }
+    let mut cache = match &args.out {
+        Some(out) => BuildCache::load(out).with_force(args.force),
+        None => BuildCache::default(),
+    };
+    let mut album = Album::import_all_photos(&args.input_directory, &mut cache, strict)?;
+    if let Some(title) = args.title.clone().or(config.title) {
+        album = album.with_title(title);
+    }
+    if let Some(manifest) = &args.manifest {
+        album.apply_manifest(manifest)?;
+    }
+
+    if let Some(config_path) = &args.out {
         println!("Value for output directory: {}", config_path.display());
         if !config_path.is_dir() {
-            if let Err(e) = fs::create_dir(&config_path) {
+            if let Err(e) = fs::create_dir(config_path) {
                 eprintln!("{:?}", e);
             }
         }
-        album.write_to_disk(&config_path);
+        let targets = export_targets(args, &assets)?;
+        album.write_to_disk(
+            config_path,
+            args.filenames,
+            &targets,
+            &assets,
+            cache,
+            strict,
+        )?;
+        if args.formats.contains(&OutputFormat::Json) {
+            album.write_model(&config_path.join("Album.json"))?;
+        }
+        build_documents(args, &targets, config_path)?;
+    }
+    Ok(())
+}
+
+/// Build the documents selected with `--formats` from the Markdown files of the given targets
+/// inside the given output directory.
+fn build_documents(
+    args: &BuildArgs,
+    targets: &[ExportTarget],
+    config_path: &Path,
+) -> io::Result<()> {
+    let steps = targets
+        .iter()
+        .filter(|target| args.formats.contains(&OutputFormat::of_target(target.kind)))
+        .filter_map(BuildStep::for_target)
+        .collect::<Vec<_>>();
+    pipeline::run_steps(&steps, config_path, args.force)
+}
 
-        // TODO add switch to disable build
-        // Build album as HTML and PDF
-        set_current_dir(&config_path).unwrap();
-        Command::new("make")
-            .spawn()
-            .expect("failed to start external executable")
-            .wait().unwrap();
+/// Initialize the metadata library and start the given number of worker threads, or one per
+/// CPU core.
+fn initialize(jobs: Option<usize>) -> io::Result<()> {
+    rexiv2::initialize()
+        .map_err(|e| io::Error::other(format!("Unable to initialize rexiv2: {}", e)))?;
+    if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .map_err(|e| io::Error::other(format!("Unable to start the worker threads: {}", e)))?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+fn main() {
+    let cli = RadalbumArgs::parse();
+    let args = match &cli.command {
+        Some(RadalbumCommand::Watch(args)) => args,
+        Some(RadalbumCommand::Preview(preview)) => &preview.build,
+        None => match &cli.build {
+            Some(args) => args,
+            None => RadalbumArgs::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "Either a command or an input directory is required",
+                )
+                .exit(),
+        },
+    };
+
+    let input_directory = &args.input_directory;
+    println!("Value for input directory: {input_directory:?}");
+
+    let result = initialize(args.jobs).and_then(|()| match &cli.command {
+        Some(RadalbumCommand::Watch(args)) => watch::watch(args, || {}),
+        Some(RadalbumCommand::Preview(preview)) => preview::preview(&preview.build, preview.port),
+        None => build(args, BuildScope::Full, false),
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        // Exit with the exit code of a failed external program, like make would
+        let code = e
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<StepFailed>())
+            .and_then(|failed| failed.code)
+            .unwrap_or(1);
+        process::exit(code);
+    }
+}
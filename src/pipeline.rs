@@ -0,0 +1,304 @@
+use crate::album::photo::export_target::{ExportTarget, TargetKind};
+use crate::album::Album;
+use clap::ValueEnum;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+use std::{env, fs, io};
+
+/// The documents the album can be built into.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// The Markdown files of all albums, without building anything from them
+    Markdown,
+    /// The HTML album, built with pandoc
+    Html,
+    /// The website with an index page and a page per day, without pandoc
+    Website,
+    /// The PDF album, laid out natively or built with pandoc and wkhtmltopdf
+    Pdf,
+    /// The EPUB album for e-readers and tablets
+    Epub,
+    /// The LaTeX document of the printed album, without building anything from it
+    Latex,
+    /// The Typst document of the printed album, without building anything from it
+    Typst,
+    /// The versioned JSON model of the album, with the detected containers and day headers
+    Json,
+}
+
+impl OutputFormat {
+    /// The format that is built from the Markdown file of the given export target.
+    pub fn of_target(kind: TargetKind) -> Self {
+        match kind {
+            TargetKind::Web => OutputFormat::Html,
+            TargetKind::Print => OutputFormat::Pdf,
+            TargetKind::Ebook => OutputFormat::Epub,
+        }
+    }
+
+    /// Whether this format needs the photos of the given export target.
+    pub fn uses_target(self, kind: TargetKind) -> bool {
+        match self {
+            OutputFormat::Markdown => true,
+            // The JSON model references the source photos
+            OutputFormat::Json => false,
+            OutputFormat::Html | OutputFormat::Website => kind == TargetKind::Web,
+            OutputFormat::Pdf | OutputFormat::Latex | OutputFormat::Typst => {
+                kind == TargetKind::Print
+            },
+            OutputFormat::Epub => kind == TargetKind::Ebook,
+        }
+    }
+}
+
+/// A build step that failed, because its program exited unsuccessfully.
+#[derive(Debug)]
+pub struct StepFailed {
+    pub output: String,
+    /// Exit code of the program, if it was not killed by a signal
+    pub code: Option<i32>,
+}
+
+impl Display for StepFailed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "Building {} failed with exit code {}", self.output, code),
+            None => write!(f, "Building {} was aborted", self.output),
+        }
+    }
+}
+
+impl Error for StepFailed {}
+
+/// Find the given program in one of the directories of the `PATH` environment variable.
+pub fn find_on_path(program: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .flat_map(|dir| {
+            let candidate = dir.join(program);
+            [candidate.with_extension("exe"), candidate]
+        })
+        .find(|candidate| candidate.is_file())
+}
+
+/// One document that is built from files inside the output directory by an external program.
+/// Like with make, the document is only rebuilt if one of its inputs is newer than the document.
+pub struct BuildStep {
+    /// File name of the built document
+    output: String,
+    /// File names of all files and directories the document is built from
+    inputs: Vec<String>,
+    program: &'static str,
+    /// Other programs that are called by the program and need to be installed as well
+    requires: Vec<&'static str>,
+    args: Vec<String>,
+}
+
+impl BuildStep {
+    /// The step that builds the HTML album from the given Markdown file, which references the
+    /// photos inside the given image directory.
+    pub fn html(markdown_file: &str, image_dir: &str) -> Self {
+        let output = "Album.html".to_string();
+        Self {
+            args: [
+                "-s",
+                "-f",
+                "gfm",
+                "-o",
+                &output,
+                "-c",
+                Album::STYLESHEET,
+                markdown_file,
+            ]
+            .map(String::from)
+            .to_vec(),
+            inputs: [markdown_file, Album::STYLESHEET, image_dir]
+                .map(String::from)
+                .to_vec(),
+            output,
+            program: "pandoc",
+            requires: vec![],
+        }
+    }
+
+    /// The step that builds the PDF album from the given Markdown file, which references the
+    /// photos inside the given image directory.
+    /// The page is 210 mm wide and as long as needed, without margins.
+    pub fn pdf(markdown_file: &str, image_dir: &str) -> Self {
+        let output = "Album.pdf".to_string();
+        let mut args = ["-s", "-f", "gfm", "-o", &output, "--pdf-engine=wkhtmltopdf"]
+            .map(String::from)
+            .to_vec();
+        args.extend(["-c", Album::STYLESHEET].map(String::from));
+        for margin in ["margin-top", "margin-left", "margin-right", "margin-bottom"] {
+            args.extend(["-V".to_string(), format!("{}=0", margin)]);
+        }
+        for option in [
+            "--page-width",
+            "210",
+            "--page-height",
+            "1189",
+            "--disable-smart-shrinking",
+            "--print-media-type",
+        ] {
+            args.push(format!("--pdf-engine-opt={}", option));
+        }
+        args.extend(["-V", "dpi=300", markdown_file].map(String::from));
+        Self {
+            args,
+            inputs: [markdown_file, Album::STYLESHEET, image_dir]
+                .map(String::from)
+                .to_vec(),
+            output,
+            program: "pandoc",
+            requires: vec!["wkhtmltopdf"],
+        }
+    }
+
+    /// The step that builds the document of the given export target from its Markdown file.
+    /// Returns none for the EPUB target and for a print target with native PDF settings, whose
+    /// documents are written together with the album itself.
+    pub fn for_target(target: &ExportTarget) -> Option<Self> {
+        match target.kind {
+            TargetKind::Web => Some(Self::html(target.markdown_file, target.image_dir)),
+            TargetKind::Print if target.pdf.is_some() => None,
+            TargetKind::Print => Some(Self::pdf(target.markdown_file, target.image_dir)),
+            TargetKind::Ebook => None,
+        }
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// The last modification of the given file, or of the given directory and all files inside
+    /// it, as photos that are overwritten with the same name do not change their directory.
+    fn newest(path: &Path) -> Option<SystemTime> {
+        let modified = Self::modified(path)?;
+        let Ok(entries) = fs::read_dir(path) else {
+            return Some(modified);
+        };
+        entries
+            .filter_map(|entry| Self::newest(&entry.ok()?.path()))
+            .chain([modified])
+            .max()
+    }
+
+    /// Check whether the document exists and is newer than all of its inputs.
+    fn is_up_to_date(&self, out_dir: &Path) -> bool {
+        let Some(output) = Self::modified(&out_dir.join(&self.output)) else {
+            return false;
+        };
+        self.inputs
+            .iter()
+            .all(|input| Self::newest(&out_dir.join(input)).is_some_and(|m| m <= output))
+    }
+
+    /// Find the given program on the `PATH`, with an error message naming this step if it is
+    /// missing.
+    fn require(&self, program: &str) -> io::Result<PathBuf> {
+        find_on_path(program).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Building {} requires {}, which was not found on the PATH. Please install it, \
+                     or leave out this document with --formats.",
+                    self.output, program
+                ),
+            )
+        })
+    }
+
+    /// Find the program of this step and all programs it requires on the `PATH`.
+    fn find_program(&self) -> io::Result<PathBuf> {
+        for program in &self.requires {
+            self.require(program)?;
+        }
+        self.require(self.program)
+    }
+
+    /// Build the document inside the given output directory, unless it is up to date.
+    fn run(&self, program: &Path, out_dir: &Path, force: bool) -> io::Result<()> {
+        if !force && self.is_up_to_date(out_dir) {
+            return Ok(());
+        }
+        println!("Building {}", self.output);
+        let status = Command::new(program)
+            .args(&self.args)
+            .current_dir(out_dir)
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(StepFailed {
+                output: self.output.clone(),
+                code: status.code(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// Run all given build steps inside the given output directory, one after another.
+/// All required programs are looked up before the first step is run, so that a missing program
+/// is reported before anything is built. With `force`, all documents are rebuilt.
+pub fn run_steps(steps: &[BuildStep], out_dir: &Path, force: bool) -> io::Result<()> {
+    let programs = steps
+        .iter()
+        .map(BuildStep::find_program)
+        .collect::<io::Result<Vec<_>>>()?;
+    for (step, program) in steps.iter().zip(programs) {
+        step.run(&program, out_dir, force)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    /// Write the given file with the given modification time, in seconds after the start of the
+    /// test. The time lies in the future, so that it is newer than all directories created by
+    /// the test.
+    fn touch(path: &Path, seconds: u64) {
+        static START: OnceLock<SystemTime> = OnceLock::new();
+        let start = START.get_or_init(|| SystemTime::now() + Duration::from_secs(3600));
+        let file = File::create(path).unwrap();
+        file.set_modified(*start + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    #[test]
+    fn documents_are_rebuilt_if_an_input_is_newer() {
+        let out = std::env::temp_dir().join(format!("radalbum-pipeline-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out);
+        fs::create_dir_all(&out).unwrap();
+        let step = BuildStep::html("Album_web.md", "img");
+        fs::create_dir(out.join("img")).unwrap();
+        touch(&out.join("Album_web.md"), 10);
+        touch(&out.join(Album::STYLESHEET), 10);
+        touch(&out.join("img/IMG_0001.jpg"), 10);
+        assert!(!step.is_up_to_date(&out), "the document is missing");
+
+        touch(&out.join("Album.html"), 20);
+        assert!(step.is_up_to_date(&out));
+
+        touch(&out.join(Album::STYLESHEET), 30);
+        assert!(!step.is_up_to_date(&out), "the stylesheet changed");
+
+        touch(&out.join("Album.html"), 40);
+        assert!(step.is_up_to_date(&out));
+
+        // Photos that are overwritten with the same name do not change their directory
+        touch(&out.join("img/IMG_0001.jpg"), 50);
+        assert!(!step.is_up_to_date(&out), "a photo changed");
+
+        touch(&out.join("Album.html"), 60);
+        fs::remove_file(out.join("Album_web.md")).unwrap();
+        assert!(!step.is_up_to_date(&out), "an input is missing");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::watch::watch;
+use crate::BuildArgs;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::{fs, io, thread};
+use tiny_http::{Header, Request, Response, Server};
+
+/// Path the live reload script polls for the number of the latest build.
+const BUILD_URL: &str = "/__radalbum/build";
+
+/// Number of threads answering requests, so that a large photo does not block the page.
+const SERVER_THREADS: usize = 4;
+
+/// Script injected into every HTML page, which reloads the page once a new build is finished.
+const LIVE_RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+    var build = null;
+    setInterval(function () {
+        fetch("/__radalbum/build", { cache: "no-store" })
+            .then(function (response) { return response.text(); })
+            .then(function (latest) {
+                if (build !== null && latest !== build) {
+                    location.reload();
+                }
+                build = latest;
+            })
+            .catch(function () {});
+    }, 1000);
+})();
+</script>
+"#;
+
+/// Decode percent-encoded characters in a URL path, e.g. `%20` into a space.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Find the file inside the output directory that is requested by the given URL.
+/// Returns none for URLs that point outside of the output directory.
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = percent_decode(url.split(['?', '#']).next().unwrap_or_default());
+    let relative = Path::new(path.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = root.join(relative);
+    if path.is_dir() {
+        // The web album is the index page of the output directory
+        let index = ["index.html", "Album.html"]
+            .iter()
+            .map(|index| path.join(index))
+            .find(|index| index.is_file());
+        return Some(index.unwrap_or(path));
+    }
+    Some(path)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("json") => "application/json",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid HTTP header")
+}
+
+/// Insert the live reload script at the end of the body of the given HTML page.
+fn inject_live_reload(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], LIVE_RELOAD_SCRIPT, &html[end..]),
+        None => format!("{}{}", html, LIVE_RELOAD_SCRIPT),
+    }
+}
+
+/// Answer a single request with the requested file of the output directory.
+fn respond(request: Request, root: &Path, build: &AtomicU64) -> io::Result<()> {
+    if request.url() == BUILD_URL {
+        let response = Response::from_string(build.load(Ordering::SeqCst).to_string())
+            .with_header(header("Content-Type", "text/plain"))
+            .with_header(header("Cache-Control", "no-store"));
+        return request.respond(response);
+    }
+    let Some(path) = resolve(root, request.url()).filter(|path| path.is_file()) else {
+        return request.respond(Response::from_string("Not Found").with_status_code(404));
+    };
+    let content_type = header("Content-Type", content_type(&path));
+    let no_cache = header("Cache-Control", "no-cache");
+    if path
+        .extension()
+        .is_some_and(|ext| ext == "html" || ext == "htm")
+    {
+        let html = inject_live_reload(&fs::read_to_string(&path)?);
+        let response = Response::from_string(html)
+            .with_header(content_type)
+            .with_header(no_cache);
+        return request.respond(response);
+    }
+    let response = Response::from_file(File::open(&path)?)
+        .with_header(content_type)
+        .with_header(no_cache);
+    request.respond(response)
+}
+
+/// Serve the output directory of the album on localhost with the given port, and rebuild the
+/// album whenever its sources change, like [watch].
+/// Every HTML page gets a small script injected, which reloads the page after each successful
+/// rebuild.
+pub fn preview(args: &BuildArgs, port: u16) -> io::Result<()> {
+    let root = args.out.clone().ok_or(io::Error::new(
+        ErrorKind::InvalidInput,
+        "Preview mode requires an output directory!",
+    ))?;
+    let server = Arc::new(
+        Server::http(("127.0.0.1", port))
+            .map_err(|e| io::Error::new(ErrorKind::AddrInUse, e.to_string()))?,
+    );
+    let build = Arc::new(AtomicU64::new(0));
+    for _ in 0..SERVER_THREADS {
+        let (server, root, build) = (server.clone(), root.clone(), build.clone());
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let url = request.url().to_string();
+                if let Err(e) = respond(request, &root, &build) {
+                    eprintln!("Could not answer request for {}: {}", url, e);
+                }
+            }
+        });
+    }
+    println!("Serving the album on http://127.0.0.1:{}/", port);
+    watch(args, || {
+        build.fetch_add(1, Ordering::SeqCst);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_characters() {
+        assert_eq!(
+            percent_decode("/Stra%C3%9Fe%20in%20%C5%81%C3%B3d%C5%BA.jpg"),
+            "/Straße in Łódź.jpg"
+        );
+        // Invalid escapes are kept as they are
+        assert_eq!(percent_decode("/100%/%zz/%2"), "/100%/%zz/%2");
+    }
+
+    #[test]
+    fn resolve_stays_inside_the_output_directory() {
+        let root = Path::new("/srv/album");
+        for url in [
+            "/../secret",
+            "/img/../../secret",
+            "/%2e%2e/secret",
+            "/%2E%2E%2Fsecret",
+            "/img/%2e%2e/%2e%2e/secret",
+        ] {
+            assert_eq!(resolve(root, url), None, "{}", url);
+        }
+        assert_eq!(
+            resolve(root, "/img/IMG%201.jpg?v=2#top"),
+            Some(root.join("img/IMG 1.jpg"))
+        );
+        assert_eq!(resolve(root, "//img/a.jpg"), Some(root.join("img/a.jpg")));
+    }
+
+    #[test]
+    fn resolve_directories_to_their_index() {
+        let root = std::env::temp_dir().join(format!("radalbum-preview-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("day")).unwrap();
+        fs::write(root.join("Album.html"), "album").unwrap();
+        assert_eq!(resolve(&root, "/"), Some(root.join("Album.html")));
+        fs::write(root.join("index.html"), "index").unwrap();
+        assert_eq!(resolve(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(resolve(&root, "/day"), Some(root.join("day")));
+    }
+}
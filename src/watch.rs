@@ -0,0 +1,120 @@
+use crate::album::assets::Assets;
+use crate::album::Album;
+use crate::{build, BuildArgs, BuildScope};
+use itertools::Itertools;
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebouncedEvent};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{fs, io, iter};
+
+/// Time to wait after a change before the album is rebuilt. All changes within this time, e.g.
+/// a Lightroom export of many photos, result in a single rebuild.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Decide which parts of the album need to be rebuilt after the given file changed.
+/// Returns none for changes that do not affect the album, e.g. temporary files or files inside
+/// the output directory. Changes of the album config or of the stylesheets inside the override
+/// directory of the assets only rebuild the stylesheet, while all other assets, e.g. templates,
+/// fonts and the LaTeX class, rewrite the whole album.
+fn affected_scope(
+    path: &Path,
+    assets: Option<&Path>,
+    config: Option<&Path>,
+    out: &Path,
+) -> Option<BuildScope> {
+    let name = path.file_name()?.to_str()?;
+    if name.starts_with('.') || name.ends_with('~') || path.starts_with(out) {
+        return None;
+    }
+    let stylesheet =
+        name == Album::STYLESHEET || (name.starts_with("theme-") && name.ends_with(".css"));
+    if config == Some(path) || (stylesheet && path.parent() == assets) {
+        Some(BuildScope::Assets)
+    } else {
+        // Photos, GPX tracks, templates, fonts and everything else
+        Some(BuildScope::Full)
+    }
+}
+
+/// Build the album and rebuild it whenever photos or other files inside the input directory, the
+/// override directory of the assets or the album config change. The override directory is only
+/// watched if it exists when watching starts.
+/// Bursts of changes are collected and result in a single rebuild. If the stylesheet changed,
+/// only the HTML and PDF album are rebuilt, all other changes rebuild the whole album, reusing
+/// all unchanged photos from the build cache.
+/// If a build fails, e.g. because a photo is only half exported, the output of the previous
+/// build is kept and the album is rebuilt again after the next change.
+/// `on_rebuild` is called after every successful build.
+pub fn watch(args: &BuildArgs, on_rebuild: impl Fn()) -> io::Result<()> {
+    let out = args.out.as_ref().ok_or(io::Error::new(
+        ErrorKind::InvalidInput,
+        "Watch mode requires an output directory!",
+    ))?;
+    match build(args, BuildScope::Full, true) {
+        Ok(()) => on_rebuild(),
+        Err(e) => eprintln!("Build failed: {}", e),
+    }
+    // The watcher reports absolute paths
+    fs::create_dir_all(out)?;
+    let out = out.canonicalize()?;
+    let input_directory = args.input_directory.canonicalize()?;
+    let assets = Assets::new(args.assets.clone())
+        .dir()
+        .map(Path::canonicalize)
+        .transpose()?;
+    let config = args.config.as_deref().map(Path::canonicalize).transpose()?;
+    let style = assets.iter().chain(&config).cloned().collect::<Vec<_>>();
+
+    let (tx, rx) = mpsc::channel();
+    let mut debouncer = new_debouncer(DEBOUNCE_TIMEOUT, tx).map_err(io::Error::other)?;
+    let watcher = debouncer.watcher();
+    watcher
+        .watch(&input_directory, RecursiveMode::NonRecursive)
+        .map_err(io::Error::other)?;
+    for path in &style {
+        // The templates are inside a subdirectory of the assets
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .map_err(io::Error::other)?;
+    }
+    println!(
+        "Watching {} for changes, press Ctrl+C to stop.",
+        iter::once(&input_directory)
+            .chain(&style)
+            .map(|path| path.display())
+            .join(", ")
+    );
+
+    for events in rx {
+        let events: Vec<DebouncedEvent> = match events {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("Watch error: {}", e);
+                continue;
+            },
+        };
+        let mut scope = None;
+        for event in &events {
+            if let Some(affected) =
+                affected_scope(&event.path, assets.as_deref(), config.as_deref(), &out)
+            {
+                println!("Changed: {}", event.path.display());
+                scope = scope.max(Some(affected));
+            }
+        }
+        let Some(scope) = scope else {
+            continue;
+        };
+        match build(args, scope, true) {
+            Ok(()) => {
+                println!("Album rebuilt.");
+                on_rebuild();
+            },
+            Err(e) => eprintln!("Build failed, keeping the previous album: {}", e),
+        }
+    }
+    Ok(())
+}